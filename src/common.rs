@@ -1,12 +1,13 @@
 use std::fmt::Display;
-use std::io;
 use std::str::FromStr;
+use std::{fs, io};
 
 use ctty::CttyError;
 use ron::error::SpannedError;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::config::{get_config, ConfigError};
 use crate::shell::keymap::TemplateError;
 
 #[derive(Error, Debug)]
@@ -17,12 +18,44 @@ pub enum VjjError {
     Ron(#[from] SpannedError),
     #[error("tty error: {0}")]
     Tty(#[from] CttyError),
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
     #[error("template error: {0}")]
     Template(#[from] TemplateError),
     #[error("boxed error: {0}")]
     Boxed(#[from] Box<dyn std::error::Error + Send + Sync + 'static>),
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
     #[error("missing keymap: {0}")]
     MisingKeymap(String),
+    #[error("{0}")]
+    Jujutsu(String),
+    #[error("mark `{0}` is not set")]
+    UnknownMark(String),
+    #[error("no revision matches `{0}`")]
+    NoMatch(String),
+    #[error("nothing searched yet, press / to search")]
+    NoSearch,
+    #[error("{0} only applies when the focused revision {1}")]
+    NotApplicable(String, String),
+    #[error("no revision selected")]
+    EmptySelection,
+    #[error("no dry run to apply")]
+    NothingPending,
+    #[error("no action is waiting for immutable revisions to be handled")]
+    NothingGuarded,
+    #[error("no jj command has been run yet")]
+    EmptyHistory,
+    #[error("a job is already running, press ctrl-c to cancel it")]
+    JobRunning,
+    #[error("not running inside a vjj session")]
+    NoSession,
+    #[error("not inside a jj repo")]
+    NoRepo,
+    #[error(
+        "`--query` is read as key presses in {0} mode, start in a mode that takes text like revset"
+    )]
+    QueryNotTaken(String),
 }
 
 pub type VjjResult<T> = Result<T, VjjError>;
@@ -34,13 +67,99 @@ pub enum Mode {
     Normal,
     Revset,
     Obslog(String),
+    Conflicts,
+    ConflictFiles(String),
+    Files(String),
+    Describe(String),
+    Move(String),
+    Confirm,
+    Workspaces,
+    AddWorkspace(String),
+    Remotes(String),
+    /// Local branches of a revision, to be pushed to a remote.
+    Branches(String, String),
+    Immutable,
+    Select,
+    Search,
+    /// Actions for a revision, opened by right-clicking it.
+    Menu(String),
+    History,
+    /// A mode defined in `modes` of the config, with the arguments it was opened with.
+    Custom(String, Vec<String>),
 }
 
+/// Names of the built-in modes, the other modes of the config are custom modes.
+const BUILTIN_MODES: [&str; 18] = [
+    "normal",
+    "revset",
+    "obslog",
+    "conflicts",
+    "conflict_files",
+    "files",
+    "describe",
+    "move",
+    "confirm",
+    "workspaces",
+    "add_workspace",
+    "remotes",
+    "branches",
+    "immutable",
+    "select",
+    "search",
+    "menu",
+    "history",
+];
+
 impl Mode {
-    pub fn header(&self) -> &str {
-        match self {
-            Mode::Normal | Mode::Obslog(_) => "Press ? for help, q to quit",
-            Mode::Revset => "Press ? for help, ctrl+c to quit",
+    pub fn header(&self) -> String {
+        get_config()
+            .ok()
+            .and_then(|config| config.modes.get(self.keymap()))
+            .map(|mode| self.expand(&mode.header))
+            .unwrap_or_default()
+    }
+
+    /// Parses the `--mode` argument, e.g. `normal`, `revset` or `obslog:<rev>`.
+    pub fn parse_arg(arg: &str) -> Result<Self, String> {
+        match arg.split_once(':') {
+            None if arg == "normal" => Ok(Mode::Normal),
+            None if arg == "revset" => Ok(Mode::Revset),
+            None if arg == "history" => Ok(Mode::History),
+            None if arg == "conflicts" => Ok(Mode::Conflicts),
+            None if arg == "workspaces" => Ok(Mode::Workspaces),
+            Some(("obslog", rev)) if !rev.is_empty() => Ok(Mode::Obslog(rev.to_string())),
+            Some(("conflict_files", rev)) if !rev.is_empty() => {
+                Ok(Mode::ConflictFiles(rev.to_string()))
+            }
+            Some(("files", rev)) if !rev.is_empty() => Ok(Mode::Files(rev.to_string())),
+            Some(("describe", rev)) if !rev.is_empty() => Ok(Mode::Describe(rev.to_string())),
+            Some(("move", rev)) if !rev.is_empty() => Ok(Mode::Move(rev.to_string())),
+            Some(("remotes", rev)) if !rev.is_empty() => Ok(Mode::Remotes(rev.to_string())),
+            _ => {
+                let (name, args) = Mode::split_custom_arg(arg);
+                match Mode::is_custom(name) {
+                    true => Ok(Mode::Custom(name.to_string(), args)),
+                    false => Err(format!(
+                        "unknown mode `{arg}`, expected one of `normal`, `revset`, `history`, \
+                         `conflicts`, `workspaces`, `obslog:<rev>`, `conflict_files:<rev>`, \
+                         `files:<rev>`, `describe:<rev>`, `move:<rev>`, `remotes:<rev>` or a \
+                         mode of the config like `<name>:<arg>` or `<name>:[\"<arg>\", ...]`"
+                    )),
+                }
+            }
+        }
+    }
+
+    /// Splits the `--mode` argument of a custom mode into its name and arguments: `<name>`,
+    /// `<name>:<arg>` or `<name>:["<arg>", ...]`. A single argument is taken as is, revsets
+    /// contain commas and colons.
+    fn split_custom_arg(arg: &str) -> (&str, Vec<String>) {
+        match arg.split_once(':') {
+            Some((name, args)) => (
+                name,
+                ron::from_str(args).unwrap_or_else(|_| vec![args.to_string()]),
+            ),
+            None => (arg, Vec::new()),
         }
     }
 
@@ -49,41 +168,171 @@ impl Mode {
             Mode::Normal => "normal",
             Mode::Revset => "revset",
             Mode::Obslog(_) => "obslog",
+            Mode::Conflicts => "conflicts",
+            Mode::ConflictFiles(_) => "conflict_files",
+            Mode::Files(_) => "files",
+            Mode::Describe(_) => "describe",
+            Mode::Move(_) => "move",
+            Mode::Confirm => "confirm",
+            Mode::Workspaces => "workspaces",
+            Mode::AddWorkspace(_) => "add_workspace",
+            Mode::Remotes(_) => "remotes",
+            Mode::Branches(..) => "branches",
+            Mode::Immutable => "immutable",
+            Mode::Select => "select",
+            Mode::Search => "search",
+            Mode::Menu(_) => "menu",
+            Mode::History => "history",
+            Mode::Custom(name, _) => name,
         }
     }
+
+    /// Whether `name` is a mode of the config that isn't built in.
+    fn is_custom(name: &str) -> bool {
+        !BUILTIN_MODES.contains(&name)
+            && get_config().is_ok_and(|config| config.modes.contains_key(name))
+    }
+
+    /// The `prompt` of a custom mode in the config, its name in uppercase by default.
+    fn prompt(name: &str) -> String {
+        get_config()
+            .ok()
+            .and_then(|config| config.modes.get(name)?.prompt.clone())
+            .unwrap_or_else(|| name.to_uppercase())
+    }
+
+    /// Splits the prompt of a custom mode into the prompt of the config and its arguments, like
+    /// `STACKS["@"]`.
+    fn split_custom_prompt(prompt: &str) -> Option<(&str, Vec<String>)> {
+        Some(match prompt.find('[') {
+            Some(index) => (&prompt[..index], ron::from_str(&prompt[index..]).ok()?),
+            None => (prompt, Vec::new()),
+        })
+    }
+
+    /// Parses the prompt of a custom mode, see [`Mode::split_custom_prompt`].
+    fn parse_custom(prompt: &str) -> Option<Mode> {
+        let (prompt, args) = Mode::split_custom_prompt(prompt)?;
+        let config = get_config().ok()?;
+        let name = config
+            .modes
+            .keys()
+            .find(|name| Mode::is_custom(name) && Mode::prompt(name) == prompt)?;
+        Some(Mode::Custom(name.clone(), args))
+    }
+
+    /// Replaces `{rev}` and `{arg:<n>}` in a value of the config of the mode.
+    pub fn expand(&self, text: &str) -> String {
+        let mut text = match self.rev() {
+            Some(rev) => text.replace("{rev}", rev),
+            None => text.to_string(),
+        };
+        if let Mode::Custom(_, args) = self {
+            for (index, arg) in args.iter().enumerate() {
+                text = text.replace(&format!("{{arg:{index}}}"), arg);
+            }
+        }
+        text
+    }
+
+    /// The revision the mode is about, like the one whose obslog is shown.
+    pub fn rev(&self) -> Option<&str> {
+        match self {
+            Mode::Obslog(rev)
+            | Mode::ConflictFiles(rev)
+            | Mode::Files(rev)
+            | Mode::Describe(rev)
+            | Mode::Move(rev)
+            | Mode::AddWorkspace(rev)
+            | Mode::Remotes(rev)
+            | Mode::Branches(rev, _)
+            | Mode::Menu(rev) => Some(rev),
+            Mode::Custom(_, args) => args.first().map(String::as_str),
+            _ => None,
+        }
+    }
+
+    /// Whether the lines of the mode are revisions, other modes list things like files.
+    pub fn lists_revisions(&self) -> bool {
+        !self.lists_files()
+            && !matches!(
+                self,
+                Mode::History
+                    | Mode::Workspaces
+                    | Mode::Remotes(_)
+                    | Mode::Branches(..)
+                    | Mode::Immutable
+                    | Mode::Menu(_)
+            )
+    }
+
+    /// Whether the lines of the mode are the paths of files in [`Mode::rev`].
+    pub fn lists_files(&self) -> bool {
+        matches!(self, Mode::ConflictFiles(_) | Mode::Files(_))
+    }
 }
 
 impl FromStr for Mode {
     type Err = SpannedError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        ron::from_str(s.split(':').next().unwrap_or_default())
+        let s = s.trim_end().trim_end_matches(':');
+        ron::from_str(s).or_else(|e| Mode::parse_custom(s).ok_or(e))
     }
 }
 
 impl Display for Mode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}: ", ron::to_string(self).unwrap().as_str())
+        match self {
+            Mode::Custom(name, args) if args.is_empty() => write!(f, "{}: ", Mode::prompt(name)),
+            Mode::Custom(name, args) => write!(
+                f,
+                "{}{}: ",
+                Mode::prompt(name),
+                ron::to_string(args).unwrap()
+            ),
+            _ => write!(f, "{}: ", ron::to_string(self).unwrap().as_str()),
+        }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// The focused and selected ids of the lines, one per line. fzf writes them to temporary files,
+/// so ids and paths with spaces or quotes arrive unchanged.
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct Selection {
     change: (String, String),
     commit: (String, String),
+    /// Whether the fields are the paths of the files fzf wrote the ids to, see [`Selection::read`].
+    #[serde(default)]
+    files: bool,
 }
 
 impl Selection {
     pub fn fzf_template(change_index: usize, commit_index: usize) -> Self {
         Self {
             change: (
-                format!("{{{change_index}}}"),
-                format!("{{+{change_index}}}"),
+                format!("{{f{change_index}}}"),
+                format!("{{+f{change_index}}}"),
             ),
             commit: (
-                format!("{{{commit_index}}}"),
-                format!("{{+{commit_index}}}"),
+                format!("{{f{commit_index}}}"),
+                format!("{{+f{commit_index}}}"),
             ),
+            files: true,
+        }
+    }
+
+    /// Reads the ids from the files fzf wrote them to, fzf removes the files once the handler is
+    /// done.
+    pub fn read(self) -> Selection {
+        if !self.files {
+            return self;
+        }
+        let read = |path: &str| fs::read_to_string(path.trim_matches('\'')).unwrap_or_default();
+        Selection {
+            change: (read(&self.change.0), read(&self.change.1)),
+            commit: (read(&self.commit.0), read(&self.commit.1)),
+            files: false,
         }
     }
 
@@ -95,8 +344,30 @@ impl Selection {
         Self::parse(&self.commit)
     }
 
+    /// The selection without the revisions whose change id is a prefix of one of `change_ids`.
+    pub fn without(&self, change_ids: &[String]) -> Selection {
+        let dropped = |id: &str| change_ids.iter().any(|change_id| change_id.starts_with(id));
+        let (change, commit) = (self.change(), self.commit());
+        let (changes, commits): (Vec<_>, Vec<_>) = change
+            .selected
+            .into_iter()
+            .zip(commit.selected)
+            .filter(|(change, _)| !dropped(change))
+            .unzip();
+        let keep_focused = change.focused.is_some_and(|focused| !dropped(&focused));
+        let focused = |ids: &(String, String)| match keep_focused {
+            true => ids.0.clone(),
+            false => String::new(),
+        };
+        Selection {
+            change: (focused(&self.change), changes.join("\n")),
+            commit: (focused(&self.commit), commits.join("\n")),
+            files: false,
+        }
+    }
+
     fn parse(ids: &(String, String)) -> IdSelection {
-        let focused = ids.0.trim_matches('\'');
+        let focused = ids.0.trim_end_matches('\n');
         IdSelection {
             focused: if focused.is_empty() {
                 None
@@ -105,8 +376,9 @@ impl Selection {
             },
             selected: ids
                 .1
-                .split_whitespace()
-                .map(|id| id.trim_matches('\'').to_string())
+                .lines()
+                .filter(|id| !id.is_empty())
+                .map(str::to_string)
                 .collect(),
         }
     }
@@ -123,3 +395,62 @@ impl IdSelection {
         self.selected.join("|")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_arg_parses_builtin_modes() {
+        assert_eq!(Mode::parse_arg("normal"), Ok(Mode::Normal));
+        assert_eq!(
+            Mode::parse_arg("obslog:@-"),
+            Ok(Mode::Obslog("@-".to_string()))
+        );
+        assert_eq!(
+            Mode::parse_arg("describe:trunk()::@"),
+            Ok(Mode::Describe("trunk()::@".to_string()))
+        );
+        assert!(Mode::parse_arg("obslog:").is_err());
+        assert!(Mode::parse_arg("not_a_mode").is_err());
+    }
+
+    #[test]
+    fn custom_args_are_a_single_arg_or_a_list() {
+        assert_eq!(Mode::split_custom_arg("stacks"), ("stacks", vec![]));
+        assert_eq!(
+            Mode::split_custom_arg("stacks:trunk()::@ | heads(all(), 1)"),
+            ("stacks", vec!["trunk()::@ | heads(all(), 1)".to_string()])
+        );
+        assert_eq!(
+            Mode::split_custom_arg(r#"stacks:["@", "a, b"]"#),
+            ("stacks", vec!["@".to_string(), "a, b".to_string()])
+        );
+    }
+
+    #[test]
+    fn builtin_prompts_round_trip() {
+        for mode in [
+            Mode::Normal,
+            Mode::Obslog("abc".to_string()),
+            Mode::Branches("@".to_string(), "origin".to_string()),
+        ] {
+            assert_eq!(mode.to_string().parse::<Mode>().unwrap(), mode);
+        }
+    }
+
+    #[test]
+    fn custom_prompts_round_trip() {
+        let args = vec!["@".to_string(), "a, \"b\"]".to_string()];
+        let prompt = Mode::Custom("no_such_mode".to_string(), args.clone()).to_string();
+        let prompt = prompt.trim_end().trim_end_matches(':');
+        assert_eq!(
+            Mode::split_custom_prompt(prompt),
+            Some(("NO_SUCH_MODE", args))
+        );
+        assert_eq!(
+            Mode::split_custom_prompt("NO_SUCH_MODE"),
+            Some(("NO_SUCH_MODE", vec![]))
+        );
+    }
+}
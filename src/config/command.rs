@@ -0,0 +1,71 @@
+use std::fs;
+
+use clap::{Args, Subcommand};
+
+use super::{get_config, validate_file, ConfigLevel};
+use crate::common::VjjResult;
+use crate::shell::exec::editor_command;
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigCommand {
+    /// List all config values
+    List,
+    /// Get the value of a config key, or all values below it
+    Get { name: String },
+    /// Print the path to a config file
+    Path(LevelArgs),
+    /// Open a config file in $EDITOR
+    Edit(LevelArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+#[group(required = true, multiple = false)]
+pub struct LevelArgs {
+    /// Target the user config file
+    #[arg(long)]
+    user: bool,
+    /// Target the config file of the current repository
+    #[arg(long)]
+    repo: bool,
+}
+
+impl LevelArgs {
+    fn level(&self) -> ConfigLevel {
+        if self.repo {
+            ConfigLevel::Repo
+        } else {
+            ConfigLevel::User
+        }
+    }
+}
+
+pub fn config_command(command: ConfigCommand) -> VjjResult<()> {
+    match command {
+        ConfigCommand::List => {
+            for (name, value) in get_config()?.entries() {
+                println!("{name} = {value}");
+            }
+        }
+        ConfigCommand::Get { name } => match get_config()?.get(&name)?.as_slice() {
+            [(key, value)] if *key == name => println!("{value}"),
+            entries => {
+                for (name, value) in entries {
+                    println!("{name} = {value}");
+                }
+            }
+        },
+        ConfigCommand::Path(args) => println!("{}", args.level().path()?.display()),
+        ConfigCommand::Edit(args) => {
+            let path = args.level().path()?;
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            if !path.exists() {
+                fs::write(&path, "(\n)\n")?;
+            }
+            editor_command(&path).status()?;
+            validate_file(&path)?;
+        }
+    }
+    Ok(())
+}
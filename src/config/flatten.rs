@@ -0,0 +1,167 @@
+//! Flattens serialized config values into the `name = value` pairs of `vjj config list`.
+//!
+//! Structs and maps are walked field by field, anything else is a leaf rendered as RON. Going
+//! through a serializer instead of `ron::Value` keeps the variant names of enums like `Pager`.
+
+use std::fmt::Display;
+
+use serde::ser::{self, Impossible, Serialize, SerializeMap, SerializeStruct, Serializer};
+
+pub fn flatten<T: Serialize + ?Sized>(name: &str, value: &T, entries: &mut Vec<(String, String)>) {
+    let flattener = Flattener {
+        name,
+        entries: &mut *entries,
+    };
+    if value.serialize(flattener).is_err() {
+        entries.push((name.to_string(), ron::to_string(value).unwrap()));
+    }
+}
+
+/// Returned as soon as a value turns out not to be a struct or a map.
+#[derive(Debug)]
+struct Leaf;
+
+impl Display for Leaf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "leaf value")
+    }
+}
+
+impl std::error::Error for Leaf {}
+
+impl ser::Error for Leaf {
+    fn custom<T: Display>(_: T) -> Self {
+        Leaf
+    }
+}
+
+struct Flattener<'a> {
+    name: &'a str,
+    entries: &'a mut Vec<(String, String)>,
+}
+
+macro_rules! leaf {
+    ($($method:ident($($arg:ty),*) -> $ok:ty;)*) => {
+        $(fn $method(self, $(_: $arg),*) -> Result<$ok, Leaf> {
+            Err(Leaf)
+        })*
+    };
+}
+
+impl<'a> Serializer for Flattener<'a> {
+    type Ok = ();
+    type Error = Leaf;
+    type SerializeSeq = Impossible<(), Leaf>;
+    type SerializeTuple = Impossible<(), Leaf>;
+    type SerializeTupleStruct = Impossible<(), Leaf>;
+    type SerializeTupleVariant = Impossible<(), Leaf>;
+    type SerializeMap = FlattenMap<'a>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Impossible<(), Leaf>;
+
+    leaf! {
+        serialize_bool(bool) -> ();
+        serialize_i8(i8) -> ();
+        serialize_i16(i16) -> ();
+        serialize_i32(i32) -> ();
+        serialize_i64(i64) -> ();
+        serialize_u8(u8) -> ();
+        serialize_u16(u16) -> ();
+        serialize_u32(u32) -> ();
+        serialize_u64(u64) -> ();
+        serialize_f32(f32) -> ();
+        serialize_f64(f64) -> ();
+        serialize_char(char) -> ();
+        serialize_str(&str) -> ();
+        serialize_bytes(&[u8]) -> ();
+        serialize_none() -> ();
+        serialize_unit() -> ();
+        serialize_unit_struct(&'static str) -> ();
+        serialize_unit_variant(&'static str, u32, &'static str) -> ();
+        serialize_seq(Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(&'static str, u32, &'static str, usize)
+            -> Self::SerializeTupleVariant;
+        serialize_struct_variant(&'static str, u32, &'static str, usize)
+            -> Self::SerializeStructVariant;
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _: &T) -> Result<(), Leaf> {
+        Err(Leaf)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: &T,
+    ) -> Result<(), Leaf> {
+        Err(Leaf)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<(), Leaf> {
+        Err(Leaf)
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<FlattenMap<'a>, Leaf> {
+        Ok(FlattenMap {
+            name: self.name,
+            entries: self.entries,
+            key: String::new(),
+        })
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self, Leaf> {
+        Ok(self)
+    }
+}
+
+impl SerializeStruct for Flattener<'_> {
+    type Ok = ();
+    type Error = Leaf;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Leaf> {
+        flatten(&format!("{}.{key}", self.name), value, self.entries);
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), Leaf> {
+        Ok(())
+    }
+}
+
+struct FlattenMap<'a> {
+    name: &'a str,
+    entries: &'a mut Vec<(String, String)>,
+    key: String,
+}
+
+impl SerializeMap for FlattenMap<'_> {
+    type Ok = ();
+    type Error = Leaf;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Leaf> {
+        let key = ron::to_string(key).unwrap();
+        self.key = ron::from_str(&key).unwrap_or(key);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Leaf> {
+        flatten(&format!("{}.{}", self.name, self.key), value, self.entries);
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), Leaf> {
+        Ok(())
+    }
+}
@@ -0,0 +1,545 @@
+pub mod command;
+mod flatten;
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use itertools::Itertools;
+use once_cell::sync::Lazy;
+use ron::error::SpannedError;
+use ron::extensions::Extensions;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use self::flatten::flatten;
+use crate::shell::diff::DiffFormat;
+use crate::shell::keymap::{Bindable, KeyMap, Keybind};
+use crate::shell::pager::Pager;
+use crate::shell::preview::PreviewTab;
+
+/// Environment variable pointing to the user config file, mirroring jj's `JJ_CONFIG`.
+pub const USER_CONFIG_ENV: &str = "VJJ_CONFIG";
+/// Environment variable used to pass `--config` layers on to the commands spawned by fzf.
+pub const CLI_CONFIG_ENV: &str = "VJJ_CLI_CONFIG";
+
+const DEFAULT_CONFIG: &str = include_str!("../../default-config.ron");
+const CONFIG_FILE_NAME: &str = "vjj.ron";
+
+static CONFIG: Lazy<Result<Config, ConfigError>> = Lazy::new(Config::load);
+
+pub fn get_config() -> Result<&'static Config, ConfigError> {
+    CONFIG.as_ref().map_err(Clone::clone)
+}
+
+#[derive(Error, Debug, Clone)]
+pub enum ConfigError {
+    #[error("failed to read {0}: {1}")]
+    Read(ConfigSource, String),
+    #[error("{0}:{1}")]
+    Parse(ConfigSource, SpannedError),
+    #[error("{0}: unknown mode `{1}` in keymap, expected one of {2}")]
+    UnknownMode(ConfigSource, String, String),
+    #[error("no config key named `{0}`")]
+    UnknownKey(String),
+    #[error("no {0} config path could be determined")]
+    NoPath(ConfigLevel),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum ConfigLevel {
+    User,
+    Repo,
+}
+
+impl ConfigLevel {
+    pub fn path(&self) -> Result<PathBuf, ConfigError> {
+        match self {
+            ConfigLevel::User => user_config_path(),
+            ConfigLevel::Repo => repo_config_path(),
+        }
+        .ok_or(ConfigError::NoPath(*self))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ConfigSource {
+    Default,
+    File(PathBuf),
+    Cli(usize),
+}
+
+impl Display for ConfigSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigSource::Default => write!(f, "<default config>"),
+            ConfigSource::File(path) => write!(f, "{}", path.display()),
+            ConfigSource::Cli(index) => write!(f, "--config #{}", index + 1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub ui: UiConfig,
+    pub log: LogConfig,
+    pub cache: CacheConfig,
+    pub history: HistoryConfig,
+    pub modes: BTreeMap<String, ModeConfig>,
+    pub keymap: KeyMap,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct UiConfig {
+    pub layout: String,
+    pub height: String,
+    pub color: String,
+    pub preview_window: String,
+    /// Falls back to jj's `ui.pager`, then `$PAGER` and finally `less` when unset.
+    pub pager: Option<Pager>,
+    /// Formats the preview cycles through, the first one is used on startup.
+    pub diff_formats: Vec<DiffFormat>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogConfig {
+    /// jj template used to render the log, it has to emit the ids in the fields below.
+    pub template: String,
+    /// Revset shown on startup, falls back to jj's `revsets.log` when unset.
+    pub revset: Option<String>,
+    pub delimiter: String,
+    pub change_field: usize,
+    pub commit_field: usize,
+    pub display_fields: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Maximum size of the preview cache of a repo in bytes, 0 disables the cache.
+    pub max_size: u64,
+    /// Render the previews of the parents and children of the focused revision in the background.
+    pub prefetch: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct HistoryConfig {
+    /// File every executed jj command is appended to, the history is only kept for the session
+    /// when unset.
+    pub file: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModeConfig {
+    pub header: String,
+    /// Preview tabs of the mode in the order they are cycled through.
+    pub preview: Vec<PreviewTab>,
+    /// jj command listing the revisions of the mode, `["log"]` when unset.
+    pub command: Option<Vec<String>>,
+    /// Template the revisions are rendered with, `log.template` when unset.
+    pub template: Option<String>,
+    /// Revset listed by the mode, `{rev}` is replaced with the revision of modes like obslog. A
+    /// revset set during the session takes precedence unless this one contains `{rev}` or
+    /// `{arg:<n>}`, `log.revset` is used when neither is set.
+    pub revset: Option<String>,
+    /// Fields of the template with the ids, `log.change_field` and `log.commit_field` when unset.
+    pub change_field: Option<usize>,
+    pub commit_field: Option<usize>,
+    /// Prompt of a custom mode, its name in uppercase when unset.
+    pub prompt: Option<String>,
+    /// jj command of the `command` preview tab, `{commit:focused}` is replaced with the focused
+    /// revision.
+    pub preview_command: Option<Vec<String>>,
+}
+
+impl Config {
+    fn load() -> Result<Self, ConfigError> {
+        let mut config: Config = parse(&ConfigSource::Default, DEFAULT_CONFIG)?;
+
+        for path in [user_config_path(), repo_config_path()]
+            .into_iter()
+            .flatten()
+        {
+            let source = ConfigSource::File(path.clone());
+            if let Some(layer) = ConfigLayer::read(&source, &path)? {
+                layer.apply(&source, &mut config)?;
+            }
+        }
+
+        for (index, text) in cli_config_layers().iter().enumerate() {
+            let source = ConfigSource::Cli(index);
+            let layer: ConfigLayer = parse(&source, text)?;
+            layer.apply(&source, &mut config)?;
+        }
+
+        Ok(config)
+    }
+
+    /// Flattened `name = value` pairs, used by `vjj config list` and `vjj config get`.
+    pub fn entries(&self) -> Vec<(String, String)> {
+        let mut entries = vec![];
+        flatten("ui", &self.ui, &mut entries);
+        flatten("log", &self.log, &mut entries);
+        flatten("cache", &self.cache, &mut entries);
+        flatten("history", &self.history, &mut entries);
+        flatten("modes", &self.modes, &mut entries);
+        for (mode, keymap) in &self.keymap {
+            for (key, keybind) in keymap {
+                entries.push((
+                    format!("keymap.{mode}.{}", ron::to_string(key).unwrap()),
+                    ron::to_string(keybind).unwrap(),
+                ));
+            }
+        }
+        entries
+    }
+
+    pub fn get(&self, name: &str) -> Result<Vec<(String, String)>, ConfigError> {
+        let prefix = format!("{name}.");
+        let entries = self
+            .entries()
+            .into_iter()
+            .filter(|(key, _)| key == name || key.starts_with(&prefix))
+            .collect_vec();
+        if entries.is_empty() {
+            return Err(ConfigError::UnknownKey(name.to_string()));
+        }
+        Ok(entries)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, default, rename = "config")]
+struct ConfigLayer {
+    ui: UiLayer,
+    log: LogLayer,
+    cache: CacheLayer,
+    history: HistoryLayer,
+    modes: BTreeMap<String, ModeLayer>,
+    /// `None` removes a binding of a lower layer.
+    keymap: BTreeMap<String, BTreeMap<Bindable, Option<Keybind>>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, default, rename = "ui")]
+struct UiLayer {
+    layout: Option<String>,
+    height: Option<String>,
+    color: Option<String>,
+    preview_window: Option<String>,
+    pager: Option<Pager>,
+    diff_formats: Option<Vec<DiffFormat>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, default, rename = "log")]
+struct LogLayer {
+    template: Option<String>,
+    revset: Option<String>,
+    delimiter: Option<String>,
+    change_field: Option<usize>,
+    commit_field: Option<usize>,
+    display_fields: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, default, rename = "cache")]
+struct CacheLayer {
+    max_size: Option<u64>,
+    prefetch: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, default, rename = "history")]
+struct HistoryLayer {
+    file: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, default, rename = "mode")]
+struct ModeLayer {
+    header: Option<String>,
+    preview: Option<Vec<PreviewTab>>,
+    command: Option<Vec<String>>,
+    template: Option<String>,
+    revset: Option<String>,
+    change_field: Option<usize>,
+    commit_field: Option<usize>,
+    prompt: Option<String>,
+    preview_command: Option<Vec<String>>,
+}
+
+impl ConfigLayer {
+    fn read(source: &ConfigSource, path: &Path) -> Result<Option<Self>, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(text) if text.trim().is_empty() => Ok(None),
+            Ok(text) => parse(source, &text).map(Some),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(ConfigError::Read(source.clone(), e.to_string())),
+        }
+    }
+
+    fn apply(self, source: &ConfigSource, config: &mut Config) -> Result<(), ConfigError> {
+        let ConfigLayer {
+            ui,
+            log,
+            cache,
+            history,
+            modes,
+            keymap,
+        } = self;
+
+        set(&mut config.ui.layout, ui.layout);
+        set(&mut config.ui.height, ui.height);
+        set(&mut config.ui.color, ui.color);
+        set(&mut config.ui.preview_window, ui.preview_window);
+        set(&mut config.ui.pager, ui.pager.map(Some));
+        set(&mut config.ui.diff_formats, ui.diff_formats);
+
+        set(&mut config.log.template, log.template);
+        set(&mut config.log.revset, log.revset.map(Some));
+        set(&mut config.log.delimiter, log.delimiter);
+        set(&mut config.log.change_field, log.change_field);
+        set(&mut config.log.commit_field, log.commit_field);
+        set(&mut config.log.display_fields, log.display_fields);
+
+        set(&mut config.cache.max_size, cache.max_size);
+        set(&mut config.cache.prefetch, cache.prefetch);
+
+        set(&mut config.history.file, history.file.map(Some));
+
+        for (name, mode) in modes {
+            let entry = config.modes.entry(name).or_insert_with(|| ModeConfig {
+                header: "Press ? for help".to_string(),
+                preview: vec![PreviewTab::Show],
+                command: None,
+                template: None,
+                revset: None,
+                change_field: None,
+                commit_field: None,
+                prompt: None,
+                preview_command: None,
+            });
+            set(&mut entry.header, mode.header);
+            set(&mut entry.preview, mode.preview);
+            set(&mut entry.command, mode.command.map(Some));
+            set(&mut entry.template, mode.template.map(Some));
+            set(&mut entry.revset, mode.revset.map(Some));
+            set(&mut entry.change_field, mode.change_field.map(Some));
+            set(&mut entry.commit_field, mode.commit_field.map(Some));
+            set(&mut entry.prompt, mode.prompt.map(Some));
+            set(&mut entry.preview_command, mode.preview_command.map(Some));
+        }
+
+        for (mode, bindings) in keymap {
+            if !config.modes.contains_key(&mode) {
+                return Err(ConfigError::UnknownMode(
+                    source.clone(),
+                    mode,
+                    config
+                        .modes
+                        .keys()
+                        .map(|name| format!("`{name}`"))
+                        .join(", "),
+                ));
+            }
+            if let Some(name) = bindings
+                .values()
+                .flatten()
+                .flat_map(|keybind| keybind.custom_modes())
+                .find(|name| !config.modes.contains_key(*name))
+            {
+                return Err(ConfigError::UnknownMode(
+                    source.clone(),
+                    name.to_string(),
+                    config
+                        .modes
+                        .keys()
+                        .map(|name| format!("`{name}`"))
+                        .join(", "),
+                ));
+            }
+            let keymap = config.keymap.entry(mode).or_default();
+            for (key, keybind) in bindings {
+                match keybind {
+                    Some(keybind) => keymap.insert(key, keybind),
+                    None => keymap.remove(&key),
+                };
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn set<T>(target: &mut T, value: Option<T>) {
+    if let Some(value) = value {
+        *target = value;
+    }
+}
+
+fn parse<T: for<'de> Deserialize<'de>>(
+    source: &ConfigSource,
+    text: &str,
+) -> Result<T, ConfigError> {
+    ron::Options::default()
+        .with_default_extension(Extensions::IMPLICIT_SOME)
+        .from_str(text)
+        .map_err(|e| ConfigError::Parse(source.clone(), e))
+}
+
+/// Checks a config file without applying it, used after `vjj config edit`.
+pub fn validate_file(path: &Path) -> Result<(), ConfigError> {
+    let source = ConfigSource::File(path.to_path_buf());
+    let mut config: Config = parse(&ConfigSource::Default, DEFAULT_CONFIG)?;
+    if let Some(layer) = ConfigLayer::read(&source, path)? {
+        layer.apply(&source, &mut config)?;
+    }
+    Ok(())
+}
+
+fn cli_config_layers() -> Vec<String> {
+    std::env::var(CLI_CONFIG_ENV)
+        .ok()
+        .and_then(|layers| ron::from_str(&layers).ok())
+        .unwrap_or_default()
+}
+
+pub fn user_config_path() -> Option<PathBuf> {
+    std::env::var_os(USER_CONFIG_ENV)
+        .map(PathBuf::from)
+        .or_else(|| Some(dirs::config_dir()?.join("vjj").join(CONFIG_FILE_NAME)))
+}
+
+pub fn repo_config_path() -> Option<PathBuf> {
+    Some(repo_dir()?.join(CONFIG_FILE_NAME))
+}
+
+/// The `.jj` directory of the workspace containing the current directory.
+pub fn workspace_jj_dir() -> Option<PathBuf> {
+    let cwd = std::env::current_dir().ok()?;
+    cwd.ancestors()
+        .map(|dir| dir.join(".jj"))
+        .find(|dir| dir.is_dir())
+}
+
+/// The `.jj/repo` directory of the repository containing the current directory.
+pub fn repo_dir() -> Option<PathBuf> {
+    let jj_dir = workspace_jj_dir()?;
+    let repo_dir = jj_dir.join("repo");
+    // Secondary workspaces store the path to the main repo instead of the repo itself.
+    let repo_dir = match fs::read_to_string(&repo_dir) {
+        Ok(path) => jj_dir.join(path.trim()),
+        Err(_) => repo_dir,
+    };
+    repo_dir.canonicalize().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layered(layers: &[&str]) -> Result<Config, ConfigError> {
+        let mut config: Config = parse(&ConfigSource::Default, DEFAULT_CONFIG)?;
+        for (index, text) in layers.iter().enumerate() {
+            let source = ConfigSource::Cli(index);
+            let layer: ConfigLayer = parse(&source, text)?;
+            layer.apply(&source, &mut config)?;
+        }
+        Ok(config)
+    }
+
+    fn value(config: &Config, name: &str) -> Option<String> {
+        config
+            .entries()
+            .into_iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+    }
+
+    #[test]
+    fn later_layers_override_earlier_ones() {
+        let config = layered(&[
+            r#"(ui: (height: "50%"), log: (revset: "all()"))"#,
+            r#"(log: (revset: "mine()"))"#,
+        ])
+        .unwrap();
+        assert_eq!(config.ui.height, "50%");
+        assert_eq!(config.log.revset.as_deref(), Some("mine()"));
+        assert_eq!(value(&config, "log.revset").unwrap(), r#"Some("mine()")"#);
+    }
+
+    #[test]
+    fn keymaps_are_merged_per_key() {
+        let config = layered(&[
+            r#"(keymap: {"normal": {"gf": ("Fetch", [jj(["git", "fetch"])])}})"#,
+            r#"(keymap: {"normal": {"u": None}})"#,
+        ])
+        .unwrap();
+        let normal = &config.keymap["normal"];
+        assert_eq!(normal[&Bindable::new("gf")].0, "Fetch");
+        assert!(!normal.contains_key(&Bindable::new("u")));
+        assert!(normal.contains_key(&Bindable::new("q")));
+    }
+
+    #[test]
+    fn custom_modes_get_defaults() {
+        let config = layered(&[r#"(modes: {"mine": (revset: "mine()")})"#]).unwrap();
+        let mode = &config.modes["mine"];
+        assert_eq!(mode.preview, vec![PreviewTab::Show]);
+        assert_eq!(mode.revset.as_deref(), Some("mine()"));
+        assert_eq!(
+            value(&config, "modes.mine.preview").unwrap(),
+            "[show]".to_string()
+        );
+    }
+
+    #[test]
+    fn entries_keep_enum_variants() {
+        let config = layered(&[r#"(ui: (pager: command(["less", "-R"])))"#]).unwrap();
+        assert_eq!(
+            value(&config, "ui.pager").unwrap(),
+            r#"Some(command(["less","-R"]))"#
+        );
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        for layer in [
+            r#"(ui: (hieght: "50%"))"#,
+            r#"(colour: "always")"#,
+            r#"(modes: {"normal": (reveset: "all()")})"#,
+        ] {
+            assert!(
+                matches!(layered(&[layer]), Err(ConfigError::Parse(..))),
+                "{layer}"
+            );
+        }
+    }
+
+    #[test]
+    fn keymaps_of_unknown_modes_are_rejected() {
+        let result = layered(&[r#"(keymap: {"nromal": {"x": ("X", [quit])}})"#]);
+        assert!(matches!(
+            result,
+            Err(ConfigError::UnknownMode(_, mode, _)) if mode == "nromal"
+        ));
+    }
+
+    #[test]
+    fn bindings_to_unknown_custom_modes_are_rejected() {
+        let result =
+            layered(&[r#"(keymap: {"normal": {"x": ("X", [mode(custom("typo", []))])}})"#]);
+        assert!(matches!(
+            result,
+            Err(ConfigError::UnknownMode(_, mode, _)) if mode == "typo"
+        ));
+    }
+}
@@ -1,5 +1,7 @@
+use std::ffi::OsStr;
 use std::fmt::Display;
-use std::io;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
 use std::ops::{Deref, DerefMut};
 use std::process::{Child, Command};
 
@@ -50,6 +52,11 @@ impl FzfBuilder {
         self
     }
 
+    pub fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(&mut self, key: K, value: V) -> &mut Self {
+        self.command.env(key, value);
+        self
+    }
+
     pub fn bind(&mut self, bind: Bind) -> &mut Self {
         self.add_flag_with_value(
             "bind",
@@ -89,8 +96,23 @@ pub enum Event {
     Change,
     Enter,
     Start,
+    Load,
     Focus,
     Esc,
+    #[strum(serialize = "ctrl-c")]
+    CtrlC,
+    #[strum(serialize = "double-click")]
+    DoubleClick,
+    #[strum(serialize = "right-click")]
+    RightClick,
+    #[strum(serialize = "scroll-up")]
+    ScrollUp,
+    #[strum(serialize = "scroll-down")]
+    ScrollDown,
+    #[strum(serialize = "preview-scroll-up")]
+    PreviewScrollUp,
+    #[strum(serialize = "preview-scroll-down")]
+    PreviewScrollDown,
 }
 
 #[derive(Debug, Clone)]
@@ -107,7 +129,11 @@ pub enum FzfAction {
     Abort,
     Up,
     Down,
+    PreviewUp,
+    PreviewDown,
+    RefreshPreview,
     ChangePrompt(Mode),
+    ChangeQuery(String),
     ChangeBorderLabel(String),
     ChangePreviewLabel(String),
     ChangePreview(VjjCommand),
@@ -121,14 +147,33 @@ pub enum FzfAction {
     ExecuteSilent(VjjCommand),
     Reload(VjjCommand),
     ReloadSync(VjjCommand),
+    Transform(FzfBindHandler),
+    /// Moves the cursor to a line, starting at 1.
+    Pos(usize),
+    Select,
+    Toggle,
+    ToggleAll,
+    DeselectAll,
 }
 
 impl FzfAction {
     fn params(&self) -> Option<String> {
         match self {
-            FzfAction::ClearQuery | FzfAction::Abort | FzfAction::Up | FzfAction::Down => None,
+            FzfAction::ClearQuery
+            | FzfAction::Abort
+            | FzfAction::Up
+            | FzfAction::Down
+            | FzfAction::PreviewUp
+            | FzfAction::PreviewDown
+            | FzfAction::RefreshPreview
+            | FzfAction::Select
+            | FzfAction::Toggle
+            | FzfAction::ToggleAll
+            | FzfAction::DeselectAll => None,
+            FzfAction::Pos(position) => Some(position.to_string()),
             FzfAction::ChangePrompt(value) => Some(value.to_string()),
             FzfAction::ChangeHeader(value)
+            | FzfAction::ChangeQuery(value)
             | FzfAction::ChangeBorderLabel(value)
             | FzfAction::ChangePreviewLabel(value) => Some(value.to_owned()),
             FzfAction::ChangePreview(command)
@@ -139,6 +184,9 @@ impl FzfAction {
             | FzfAction::ReloadSync(command) => {
                 Some(VjjShellExpression::Command(command.clone()).to_string())
             }
+            FzfAction::Transform(handler) => {
+                Some(VjjShellExpression::Handler(handler.clone()).to_string())
+            }
             FzfAction::Execute {
                 command,
                 interactive,
@@ -163,3 +211,27 @@ impl Display for FzfAction {
         }
     }
 }
+
+/// Sends actions to the fzf instance this process was started from, requires fzf to be started
+/// with `--listen`.
+pub fn post_actions(actions: &[FzfAction]) -> io::Result<()> {
+    let port: u16 = std::env::var("FZF_PORT")
+        .ok()
+        .and_then(|port| port.parse().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "FZF_PORT is not set"))?;
+    let body = actions.iter().join("+");
+    let api_key = std::env::var("FZF_API_KEY")
+        .map(|key| format!("x-api-key: {key}\r\n"))
+        .unwrap_or_default();
+
+    let mut stream = TcpStream::connect(("127.0.0.1", port))?;
+    write!(
+        stream,
+        "POST / HTTP/1.1\r\nHost: localhost\r\n{api_key}Content-Length: {}\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()?;
+    // fzf answers once the actions are queued, wait for that before the next request.
+    let _ = stream.read(&mut [0; 512])?;
+    Ok(())
+}
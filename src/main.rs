@@ -1,70 +1,172 @@
 pub mod common;
+mod config;
 mod fzf;
+mod session;
 mod shell;
 
-use clap::Parser;
-use common::{Mode, Selection};
+use std::process::ExitStatus;
+
+use clap::{Parser, Subcommand};
+use common::{Mode, Selection, VjjError, VjjResult};
+use config::command::{config_command, ConfigCommand};
+use config::{get_config, CLI_CONFIG_ENV};
 use fzf::{Bind, Event, Fzf, FzfAction};
+use session::{random_token, Session, SessionDir};
 use shell::command::VjjCommand;
+use shell::dry_run::remove_stale_copies;
 use shell::fzf_binding::{FzfBindHandler, InputKind};
-use shell::keymap::which_key;
+use shell::keymap::{takes_query, which_key};
+use shell::pick::{PickFormat, PICK_CANCELLED, PICK_FORMAT_ENV};
+use shell::preview::PreviewTab;
 use shell::{vjj_shell, VjjShellExpression};
 
-/// Simple program to greet a person
+/// Visual Jujutsu, an fzf based TUI for jj
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-    #[arg(short)]
-    command: Option<VjjShellExpression>,
+    #[arg(short = 'c', hide = true)]
+    expression: Option<VjjShellExpression>,
+    /// Additional config in RON, applied on top of the user and repo config
+    #[arg(long, value_name = "RON", global = true)]
+    config: Vec<String>,
+    #[command(flatten)]
+    startup: StartupArgs,
+    #[command(subcommand)]
+    command: Option<VjjSubcommand>,
+}
+
+#[derive(clap::Args, Debug)]
+struct StartupArgs {
+    /// Revset to show on startup instead of `log.revset`
+    #[arg(short = 'r', long, visible_alias = "revset", value_name = "REVSET")]
+    revisions: Option<String>,
+    /// Mode to start in: `normal`, `revset`, `history`, `conflicts`, `workspaces`, `obslog:<rev>`,
+    /// `conflict_files:<rev>`, `files:<rev>`, `describe:<rev>`, `move:<rev>`, `remotes:<rev>` or a
+    /// custom mode like `<name>:<arg>` or `<name>:["<arg>", ...]`
+    #[arg(long, value_parser = Mode::parse_arg, default_value = "normal")]
+    mode: Mode,
+    /// Initial query, e.g. the revset to edit when starting in revset mode. Only modes that read
+    /// the query as text take one
+    #[arg(long)]
+    query: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum VjjSubcommand {
+    /// Manage the vjj config
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Pick revisions and print them to stdout, exits with 130 when cancelled
+    Pick {
+        #[command(flatten)]
+        startup: StartupArgs,
+        /// Allow selecting multiple revisions
+        #[arg(long)]
+        multi: bool,
+        /// How the picked revisions are printed
+        #[arg(long, value_enum, default_value_t)]
+        format: PickFormat,
+    },
 }
 
 fn main() {
     let args = Args::parse();
-    // println!(
-    //     "{}",
-    //     VjjShellExpression::Command(VjjCommand::Show(Selection::fzf_template(2,
-    // 3,))) );
 
-    if let Some(expression) = args.command {
+    if !args.config.is_empty() {
+        std::env::set_var(CLI_CONFIG_ENV, ron::to_string(&args.config).unwrap());
+    }
+
+    if let Some(expression) = args.expression {
         vjj_shell(expression);
         return;
     }
 
-    let mode = Mode::default();
+    let result = match args.command {
+        Some(VjjSubcommand::Config(command)) => config_command(command),
+        Some(VjjSubcommand::Pick {
+            startup,
+            multi,
+            format,
+        }) => match run(startup, Some((format, multi))) {
+            Ok(status) => std::process::exit(status.code().unwrap_or(PICK_CANCELLED)),
+            Err(e) => Err(e),
+        },
+        None => run(args.startup, None).map(|_| ()),
+    };
+    if let Err(e) = result {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+fn run(startup: StartupArgs, pick: Option<(PickFormat, bool)>) -> VjjResult<ExitStatus> {
+    let config = get_config()?;
+    let _session = SessionDir::create()?;
+    remove_stale_copies();
+    let StartupArgs {
+        revisions: revset,
+        mode,
+        query,
+    } = startup;
+    if query.is_some() && !takes_query(&mode)? {
+        return Err(VjjError::QueryNotTaken(mode.keymap().to_string()));
+    }
+
+    let (change_index, commit_index) = (config.log.change_field, config.log.commit_field);
 
-    let (change_index, commit_index) = (2, 3);
+    // `log.revset` isn't copied into the session, it comes after the revsets of the modes.
+    let session = Session::update(|session| session.revset = revset)?;
 
-    let mut fzf = Fzf::build()
+    let mut fzf = Fzf::build();
+    fzf.add_flag_with_value("border-label", session.border_label());
+    if let Some(query) = query {
+        fzf.add_flag_with_value("query", query);
+    }
+    match pick {
+        Some((format, multi)) => {
+            fzf.env(PICK_FORMAT_ENV, format.to_string());
+            if multi {
+                fzf.add_flag("multi");
+            }
+        }
+        None => {
+            fzf.add_flag("multi");
+        }
+    }
+    let mut fzf = fzf
         .add_flag("ansi")
         .add_flag("no-cycle")
         .add_flag("no-sort")
         .add_flag("no-info")
-        .add_flag("multi")
         .add_flag("phony")
-        .add_flag_with_value("color", "header:-1")
-        .add_flag_with_value("with-nth", "1,4..")
-        .add_flag_with_value("layout", "reverse-list")
-        .add_flag_with_value("height", "100%")
-        .add_flag_with_value("preview-window", "right,wrap")
-        .add_flag_with_value("preview-label", "Preview (jj show)")
-        .add_flag_with_value("delimiter", "\u{200B}")
-        .add_flag_with_value("prompt", &mode.to_string())
+        // Lets background jobs push their progress to fzf, other processes don't know the key.
+        .add_flag("listen")
+        .env("FZF_API_KEY", random_token()?)
+        .add_flag_with_value("color", &config.ui.color)
+        .add_flag_with_value("with-nth", &config.log.display_fields)
+        .add_flag_with_value("layout", &config.ui.layout)
+        .add_flag_with_value("height", &config.ui.height)
+        .add_flag_with_value("preview-window", &config.ui.preview_window)
+        .add_flag_with_value(
+            "preview-label",
+            PreviewTab::current(&mode).preview_label(&mode),
+        )
+        .add_flag_with_value("delimiter", &config.log.delimiter)
+        .add_flag_with_value("prompt", mode.to_string())
         .add_flag_with_value(
             "header",
-            which_key(&mode, "").unwrap_or(mode.header().to_string()),
+            which_key(&mode, "", None).unwrap_or(mode.header()),
         )
         .add_flag_with_value(
             "preview",
-            &VjjShellExpression::Command(VjjCommand::Show(commit_index.to_string())),
+            VjjShellExpression::Command(
+                PreviewTab::current(&mode).command(format!("{{{commit_index}}}")),
+            ),
         )
         .bind(Bind::Actions(
             Event::Start,
             vec![FzfAction::Reload(VjjCommand::Log)],
         ))
-        .bind(Bind::Actions(
-            Event::Start,
-            vec![FzfAction::Reload(VjjCommand::Log)],
-        ))
         .bind(Bind::Transform(
             Event::Change,
             FzfBindHandler::Input {
@@ -86,6 +188,40 @@ fn main() {
                 selection: Selection::fzf_template(change_index, commit_index),
             },
         ))
+        .bind(Bind::Transform(
+            Event::CtrlC,
+            FzfBindHandler::Input {
+                kind: InputKind::CtrlC,
+                selection: Selection::fzf_template(change_index, commit_index),
+            },
+        ))
+        .bind(Bind::Transform(
+            Event::DoubleClick,
+            FzfBindHandler::Input {
+                kind: InputKind::DoubleClick,
+                selection: Selection::fzf_template(change_index, commit_index),
+            },
+        ))
+        .bind(Bind::Transform(
+            Event::RightClick,
+            FzfBindHandler::Input {
+                kind: InputKind::RightClick,
+                selection: Selection::fzf_template(change_index, commit_index),
+            },
+        ))
+        // Scrolling the list moves the cursor like the arrow keys, so the focus handler skips
+        // lines without a revision in the direction of the scroll.
+        .bind(Bind::Actions(Event::ScrollUp, vec![FzfAction::Up]))
+        .bind(Bind::Actions(Event::ScrollDown, vec![FzfAction::Down]))
+        .bind(Bind::Actions(
+            Event::PreviewScrollUp,
+            vec![FzfAction::PreviewUp],
+        ))
+        .bind(Bind::Actions(
+            Event::PreviewScrollDown,
+            vec![FzfAction::PreviewDown],
+        ))
+        .bind(Bind::Transform(Event::Load, FzfBindHandler::Load))
         .bind(Bind::Transform(
             Event::Focus,
             FzfBindHandler::Focus {
@@ -94,7 +230,6 @@ fn main() {
                 action: "{fzf:action}".to_string(),
             },
         ))
-        .spawn()
-        .unwrap();
-    fzf.wait().unwrap();
+        .spawn()?;
+    Ok(fzf.wait()?)
 }
@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+use std::fs::{self, DirBuilder, File};
+use std::io::{self, Read};
+use std::os::unix::fs::DirBuilderExt;
+use std::path::PathBuf;
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::common::VjjResult;
+use crate::config::get_config;
+use crate::shell::diff::DiffFormat;
+use crate::shell::immutable::Guard;
+use crate::shell::job::Job;
+use crate::shell::pager::Pager;
+use crate::shell::preview::PreviewTab;
+
+/// Environment variable pointing the commands spawned by fzf to the session directory.
+pub const SESSION_ENV: &str = "VJJ_SESSION";
+
+/// State shared between the short lived `vjj -c` invocations of a single vjj session.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Session {
+    pub revset: Option<String>,
+    pub diff_format: Option<DiffFormat>,
+    pub preview_tab: Option<PreviewTab>,
+    pub job: Option<Job>,
+    /// Arguments of the jj command waiting for confirmation in the confirm mode.
+    pub pending: Option<Vec<String>>,
+    /// Workspace whose working-copy commit `{workspace:wc}` refers to, the current one if unset.
+    pub workspace: Option<String>,
+    /// Key waiting for the user to decide what to do about the immutable revisions it rewrites.
+    pub guard: Option<Guard>,
+    /// Full change ids of the marks by name.
+    pub marks: BTreeMap<String, String>,
+    /// Revision to move the cursor to once the log has been reloaded.
+    pub focus: Option<String>,
+    /// Text of the last search, repeated by `search_next` and `search_previous`.
+    pub search: Option<String>,
+    /// Pager found in jj's config or `$PAGER` by the first command that needed one.
+    pub pager: Option<Pager>,
+    /// Format jj's config resolves the `default` diff format to, looked up like the pager.
+    pub default_diff_format: Option<DiffFormat>,
+}
+
+impl Session {
+    pub fn load() -> Session {
+        Self::path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|state| ron::from_str(&state).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn update<F: FnOnce(&mut Session)>(update: F) -> VjjResult<Session> {
+        // Jobs, reloads and key handlers update the session concurrently, hold the lock from
+        // loading to storing so none of their updates get lost.
+        let _lock = match Self::dir() {
+            Some(dir) => {
+                let lock = File::create(dir.join("session.lock"))?;
+                lock.lock()?;
+                Some(lock)
+            }
+            None => None,
+        };
+        let mut session = Self::load();
+        update(&mut session);
+        if let Some(path) = Self::path() {
+            // Jobs update the session in the background, so never leave a partial file behind.
+            let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+            fs::write(&tmp, ron::to_string(&session).unwrap())?;
+            fs::rename(tmp, path)?;
+        }
+        Ok(session)
+    }
+
+    /// Directory for files belonging to the session, like the output of jobs.
+    pub fn dir() -> Option<PathBuf> {
+        std::env::var_os(SESSION_ENV).map(PathBuf::from)
+    }
+
+    /// Border label showing the revset, the targeted workspace and the status of the current job.
+    pub fn border_label(&self) -> String {
+        [
+            self.revset
+                .clone()
+                .or_else(|| get_config().ok()?.log.revset.clone()),
+            self.workspace.as_ref().map(|name| format!("wc: {name}@")),
+            self.job.as_ref().and_then(Job::status_label),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|part| format!(" {part} "))
+        .join("│")
+    }
+
+    fn path() -> Option<PathBuf> {
+        Some(Self::dir()?.join("session.ron"))
+    }
+}
+
+/// Directory holding the state of a running session, removed again when dropped.
+pub struct SessionDir(PathBuf);
+
+impl SessionDir {
+    /// Creates a new directory only the user can access, with a random name so other users can't
+    /// create it in advance.
+    pub fn create() -> VjjResult<SessionDir> {
+        loop {
+            let dir = std::env::temp_dir().join(format!("vjj-{}", random_token()?));
+            match DirBuilder::new().mode(0o700).create(&dir) {
+                Ok(()) => {
+                    std::env::set_var(SESSION_ENV, &dir);
+                    return Ok(SessionDir(dir));
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+impl Drop for SessionDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Random hex string read from the OS, used for names and keys other users mustn't guess.
+pub fn random_token() -> VjjResult<String> {
+    let mut bytes = [0u8; 16];
+    File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
@@ -0,0 +1,150 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
+
+use itertools::Itertools;
+
+use super::command::VjjCommand;
+use super::preview::PreviewTab;
+use super::VjjShellExpression;
+use crate::common::{Mode, VjjResult};
+use crate::config::{get_config, repo_dir};
+
+/// On disk cache of rendered previews, only used for output that can't change for a given commit.
+pub struct PreviewCache {
+    dir: PathBuf,
+    max_size: u64,
+}
+
+impl PreviewCache {
+    /// Opens the cache of the current repo, `None` if caching is disabled or there is no repo.
+    pub fn open() -> Option<PreviewCache> {
+        let max_size = get_config().ok()?.cache.max_size;
+        if max_size == 0 {
+            return None;
+        }
+        let mut hasher = DefaultHasher::new();
+        repo_dir()?.hash(&mut hasher);
+        let dir = dirs::cache_dir()?
+            .join("vjj")
+            .join(format!("{:016x}", hasher.finish()));
+        fs::create_dir_all(&dir).ok()?;
+        Some(PreviewCache { dir, max_size })
+    }
+
+    pub fn get(&self, commit: &str, kind: &str) -> Option<Vec<u8>> {
+        if !is_full_commit_id(commit) {
+            return None;
+        }
+        let path = self.path(commit, kind);
+        let content = fs::read(&path).ok()?;
+        // The modification time doubles as the last access time for the eviction.
+        if let Ok(file) = File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(content)
+    }
+
+    pub fn put(&self, commit: &str, kind: &str, content: &[u8]) -> VjjResult<()> {
+        if !is_full_commit_id(commit) {
+            return Ok(());
+        }
+        let path = self.path(commit, kind);
+        // Write to a temporary file first so concurrent readers never see partial output.
+        let tmp = path.with_extension(format!("{kind}.{}.tmp", std::process::id()));
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, &path)?;
+        self.evict()
+    }
+
+    /// Removes the least recently used entries until the cache fits into `cache.max_size`.
+    fn evict(&self) -> VjjResult<()> {
+        let entries = fs::read_dir(&self.dir)?
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                if entry.file_name() == "prefetch.lock" {
+                    return None;
+                }
+                let metadata = entry.metadata().ok()?;
+                Some((entry.path(), metadata.len(), metadata.modified().ok()?))
+            })
+            .sorted_by_key(|(_, _, modified)| *modified)
+            .collect_vec();
+        let mut size: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        for (path, len, _) in entries {
+            if size <= self.max_size {
+                break;
+            }
+            if fs::remove_file(path).is_ok() {
+                size -= len;
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes sure only one prefetch runs at a time, stale locks older than a minute are ignored.
+    pub fn lock_prefetch(&self) -> Option<PrefetchLock> {
+        let path = self.dir.join("prefetch.lock");
+        let stale = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|modified| {
+                modified
+                    .elapsed()
+                    .is_ok_and(|elapsed| elapsed.as_secs() > 60)
+            });
+        if stale {
+            let _ = fs::remove_file(&path);
+        }
+        File::options()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .ok()?;
+        Some(PrefetchLock(path))
+    }
+
+    fn path(&self, commit: &str, kind: &str) -> PathBuf {
+        self.dir.join(format!("{commit}.{kind}"))
+    }
+}
+
+pub struct PrefetchLock(PathBuf);
+
+impl Drop for PrefetchLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Entries are keyed by full commit ids, a shortest prefix can come to match another commit once
+/// more commits exist.
+fn is_full_commit_id(commit: &str) -> bool {
+    commit.len() == 40 && commit.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Renders the previews of the parents and children of `commit` in a detached process, unless
+/// the active tab of `mode` isn't cached.
+pub fn spawn_prefetch(mode: &Mode, commit: &str) {
+    if !get_config().is_ok_and(|config| config.cache.prefetch && config.cache.max_size > 0)
+        || !is_full_commit_id(commit)
+        || !matches!(
+            PreviewTab::current(mode).jj_args(commit, true),
+            Some((_, Some(_)))
+        )
+    {
+        return;
+    }
+    let Ok(exe) = std::env::current_exe() else {
+        return;
+    };
+    let _ = Command::new(exe)
+        .arg("-c")
+        .arg(VjjShellExpression::Command(VjjCommand::Prefetch(commit.to_string())).to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+}
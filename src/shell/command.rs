@@ -1,15 +1,38 @@
+use std::ffi::OsStr;
 use std::fmt::Display;
-use std::io::Write;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Command, Output, Stdio};
 use std::str::FromStr;
 
+use ansi_term::Color;
 use itertools::Itertools;
 use ron::error::SpannedError;
 use serde::{Deserialize, Serialize};
 
-use super::exec::{get_pager, jj, Pager};
+use super::cache::PreviewCache;
+use super::condition::{properties, Property};
+use super::conflicts::{print_conflict_files, print_conflict_markers};
+use super::describe::describe_many;
+use super::dry_run::{insert_args, print_dry_run, simulate};
+use super::exec::{jj, jj_output, jj_plain_output};
+use super::files::{print_file_diff, print_files};
+use super::focus::record_log_ids;
+use super::history::{print_history, print_output, HistoryEntry};
+use super::immutable::print_guarded;
+use super::job::{print_job_output, run_job};
 use super::keymap::{get_keymap, Keybind};
+use super::marks::MarkColumn;
+use super::menu::{print_binding, print_menu};
+use super::pager::Pager;
+use super::pick::{pick_output, PickFormat};
+use super::preview::PreviewTab;
+use super::remote::{print_branch_push_dry_run, print_branches, print_push_dry_run, print_remotes};
+use super::select::line_change_id;
+use super::workspace::print_workspaces;
 use super::ShellContext;
 use crate::common::{Mode, VjjError, VjjResult};
+use crate::config::get_config;
+use crate::session::Session;
 
 pub fn vjj_command(
     command: VjjCommand,
@@ -18,35 +41,27 @@ pub fn vjj_command(
     interactive: bool,
 ) -> VjjResult<()> {
     match command {
-        VjjCommand::Show(rev) => {
-            let rev = rev.trim_matches('\'');
-            if !rev.is_empty() {
-                jj(["--ignore-working-copy", "show", &rev], pager, interactive)?;
-            }
-        }
+        VjjCommand::Show(rev) => preview(PreviewTab::Show, &rev, pager, interactive)?,
+        VjjCommand::DiffStat(rev) => preview(PreviewTab::DiffStat, &rev, pager, interactive)?,
+        VjjCommand::Evolution(rev) => preview(PreviewTab::Obslog, &rev, pager, interactive)?,
+        VjjCommand::Descendants(rev) => preview(PreviewTab::Descendants, &rev, pager, interactive)?,
+        VjjCommand::Conflicts(rev) => preview(PreviewTab::Conflicts, &rev, pager, interactive)?,
+        VjjCommand::Prefetch(rev) => prefetch(&ctx.mode, &rev)?,
         VjjCommand::Log => {
-            let log_args = [
-                "--ignore-working-copy",
-                "--config-toml",
-                include_str!("../../jj-config-overrides.toml"),
-            ];
-            match ctx.mode {
-                Mode::Normal | Mode::Revset => match ctx.revset {
-                    Some(revset) => jj(
-                        log_args.iter().chain(&["log", "-r", revset.as_str()]),
-                        pager,
-                        interactive,
-                    )?,
-                    None => jj(log_args.iter().chain(&["log"]), pager, interactive)?,
-                },
-                Mode::Obslog(rev) => jj(
-                    log_args.iter().chain(&["obslog", "-r", rev.as_str()]),
-                    pager,
-                    interactive,
-                )?,
-            }
+            // Only lists printed by `log` have ids, don't leave the ids of another list behind.
+            record_log_ids(&[])?;
+            match &ctx.mode {
+                Mode::ConflictFiles(rev) => return print_conflict_files(rev),
+                Mode::Files(rev) => return print_files(rev),
+                Mode::History => return print_history(),
+                Mode::Workspaces => return print_workspaces(),
+                Mode::Remotes(_) => return print_remotes(),
+                Mode::Branches(rev, _) => return print_branches(rev),
+                Mode::Menu(_) => return print_menu(ctx),
+                mode => list_revisions(mode, ctx.revset, pager, interactive)?,
+            };
         }
-        VjjCommand::Help => {
+        VjjCommand::Help(unavailable) => {
             let keymap = match get_keymap(&ctx.mode) {
                 Ok(keymap) => keymap,
                 Err(VjjError::MisingKeymap(_)) => {
@@ -74,11 +89,17 @@ pub fn vjj_command(
                     ctx.mode.keymap().to_uppercase(),
                     keymap
                         .iter()
-                        .map(|(key, Keybind(help, _))| format!(
-                            "{:width$}  {help}",
-                            key.to_string(),
-                            width = max_len
-                        ))
+                        .map(|(key, Keybind(help, _, _, when))| {
+                            let key = key.to_string();
+                            let line = match when {
+                                Some(when) => format!("{key:max_len$}  {help} (if it {when})"),
+                                None => format!("{key:max_len$}  {help}"),
+                            };
+                            match unavailable.contains(&key) {
+                                true => Color::Fixed(8).paint(line).to_string(),
+                                false => line,
+                            }
+                        })
                         .join("\n")
                 )),
                 ctx,
@@ -88,29 +109,78 @@ pub fn vjj_command(
         }
         VjjCommand::Output(output) => {
             if pager {
-                let Pager {
-                    mut child,
-                    mut stdin,
-                } = get_pager()?;
-                writeln!(stdin, "{}", output)?;
-                drop(stdin);
-                child.wait()?;
+                Pager::resolve()?.page(format!("{output}\n").as_bytes())?;
             } else {
                 println!("{}", output);
             }
         }
         VjjCommand::Jujutsu(args) => {
-            jj(args, pager, interactive)?;
+            let output = jj(&args, pager, interactive)?;
+            let mut entry =
+                HistoryEntry::new(args, output.status.code(), &output.stdout, &output.stderr);
+            // Without the pager jj prints straight to the terminal.
+            entry.uncaptured = !pager;
+            entry.record()?;
+        }
+        VjjCommand::HistoryOutput(index) => print_output(&index)?,
+        VjjCommand::DescribeMany {
+            revs,
+            ignore_immutable,
+        } => describe_many(revs, ignore_immutable)?,
+        VjjCommand::InsertPreview { target, before } => {
+            if let Some(rev) = ctx.mode.rev() {
+                print_dry_run(insert_args(rev, &target, before))?
+            }
+        }
+        VjjCommand::Simulate(args) => simulate(args)?,
+        VjjCommand::Guarded => print_guarded()?,
+        VjjCommand::Binding(key) => print_binding(&ctx.mode, &key)?,
+        VjjCommand::CommandPreview(rev) => command_preview(&ctx.mode, &rev, pager, interactive)?,
+        VjjCommand::PushDryRun { remote, change } => {
+            if let Some(rev) = ctx.mode.rev() {
+                print_push_dry_run(&remote, rev, change)?
+            }
+        }
+        VjjCommand::BranchPushDryRun(branch) => {
+            if let Mode::Branches(_, remote) = &ctx.mode {
+                print_branch_push_dry_run(remote, &branch)?
+            }
+        }
+        VjjCommand::WorkspaceShow(name) => {
+            let name = name.trim_matches('\'');
+            if !name.is_empty() {
+                jj(
+                    ["--ignore-working-copy", "show", &format!("{name}@")],
+                    pager,
+                    interactive,
+                )?;
+            }
+        }
+        VjjCommand::PendingDryRun => {
+            if let Some(args) = Session::load().pending {
+                print_dry_run(args)?
+            }
         }
+        VjjCommand::ConflictMarkers(path) => {
+            if let Some(rev) = ctx.mode.rev() {
+                print_conflict_markers(rev, &path)?
+            }
+        }
+        VjjCommand::FileDiff(path) => {
+            if let Some(rev) = ctx.mode.rev() {
+                print_file_diff(rev, &path)?
+            }
+        }
+        VjjCommand::Job(args) => run_job(args)?,
+        VjjCommand::JobOutput => print_job_output()?,
+        VjjCommand::Pick {
+            format,
+            change,
+            commit,
+        } => pick_output(format, change, commit)?,
         VjjCommand::Error(output) => {
             if pager {
-                let Pager {
-                    mut child,
-                    mut stdin,
-                } = get_pager()?;
-                writeln!(stdin, "{}", output)?;
-                drop(stdin);
-                child.wait()?;
+                Pager::resolve()?.page(format!("{output}\n").as_bytes())?;
             } else {
                 eprintln!("{}", output);
             }
@@ -119,14 +189,291 @@ pub fn vjj_command(
     Ok(())
 }
 
+/// Prints a line of a mode that doesn't list revisions, `id` ends up in the change and commit
+/// fields so the selection templates work like in the log.
+pub fn print_line(id: &str, text: &str) -> VjjResult<()> {
+    let log = &get_config()?.log;
+    let mut fields = vec![String::new(); log.change_field.max(log.commit_field).max(1)];
+    fields[log.change_field.saturating_sub(1)] = id.to_string();
+    fields[log.commit_field.saturating_sub(1)] = id.to_string();
+    fields.push(text.to_string());
+    println!("{}", fields.join(&log.delimiter));
+    Ok(())
+}
+
+/// Lists the revisions of `mode` with the command, template, revset and id fields of its config,
+/// falling back to `log` for the ones it doesn't set. The revset of the session takes precedence
+/// over the one of the mode, unless the mode's is about its revision like `{rev}` in obslog.
+fn list_revisions(
+    mode: &Mode,
+    revset: Option<String>,
+    pager: bool,
+    interactive: bool,
+) -> VjjResult<Output> {
+    let config = get_config()?;
+    let mode_config = config.modes.get(mode.keymap());
+    let template = mode_config
+        .and_then(|mode_config| mode_config.template.clone())
+        .unwrap_or(config.log.template.clone());
+    let mode_revset = mode_config.and_then(|mode_config| mode_config.revset.as_deref());
+    let parameterised = mode_revset
+        .is_some_and(|mode_revset| mode_revset.contains("{rev}") || mode_revset.contains("{arg:"));
+    let mode_revset = mode_revset.map(|mode_revset| mode.expand(mode_revset));
+    let revset = match parameterised {
+        true => mode_revset,
+        false => revset.or(mode_revset),
+    }
+    .or(config.log.revset.clone());
+
+    let mut args = vec![
+        "--ignore-working-copy".to_string(),
+        "--config-toml".to_string(),
+        format!("templates.log = {}", toml::Value::String(template)),
+    ];
+    match mode_config.and_then(|mode_config| mode_config.command.clone()) {
+        Some(command) => args.extend(command.iter().map(|arg| mode.expand(arg))),
+        None => args.push("log".to_string()),
+    }
+    if let Some(revset) = revset {
+        args.extend(["-r".to_string(), revset]);
+    }
+    let fields = (
+        mode_config
+            .and_then(|mode_config| mode_config.change_field)
+            .unwrap_or(config.log.change_field),
+        mode_config
+            .and_then(|mode_config| mode_config.commit_field)
+            .unwrap_or(config.log.commit_field),
+    );
+    log(args, fields, pager, interactive)
+}
+
+/// Runs `jj log`, adding a column with the marks of each revision when there are any, and
+/// records the change id of each line for actions moving the cursor. The ids are moved from
+/// `fields` to the fields of `log` the bindings read them from. Lines are passed on to fzf as jj
+/// prints them, so large logs show up right away.
+fn log<I, S>(args: I, fields: (usize, usize), pager: bool, interactive: bool) -> VjjResult<Output>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    if pager || interactive {
+        return jj(args, pager, interactive);
+    }
+    let mut child = Command::new("jj")
+        .arg("--color=always")
+        .arg("--no-pager")
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let marks = MarkColumn::load();
+    let mut ids = vec![];
+    let mut stdout = io::stdout().lock();
+    if let Some(output) = child.stdout.take() {
+        for line in BufReader::new(output).split(b'\n') {
+            let line = move_id_fields(&String::from_utf8_lossy(&line?), fields)?;
+            let id = line_change_id(&line)?;
+            writeln!(stdout, "{}", marks.format(&line, id.as_deref()))?;
+            ids.push(id);
+        }
+    }
+    let status = child.wait()?;
+    record_log_ids(&ids)?;
+    Ok(Output {
+        status,
+        stdout: vec![],
+        stderr: vec![],
+    })
+}
+
+/// Moves the change and commit ids of a line from `(change_field, commit_field)` to the fields of
+/// `log`, leaving lines without ids like descriptions alone.
+fn move_id_fields(line: &str, (change_field, commit_field): (usize, usize)) -> VjjResult<String> {
+    let log = &get_config()?.log;
+    let mut fields = line.split(log.delimiter.as_str()).collect_vec();
+    if (change_field, commit_field) == (log.change_field, log.commit_field)
+        || fields.len() < change_field.max(commit_field)
+        || change_field == commit_field
+        || change_field == 0
+        || commit_field == 0
+    {
+        return Ok(line.to_string());
+    }
+    let change = fields[change_field - 1];
+    let commit = fields[commit_field - 1];
+    fields.remove(change_field.max(commit_field) - 1);
+    fields.remove(change_field.min(commit_field) - 1);
+    let mut ids = [(log.change_field, change), (log.commit_field, commit)];
+    ids.sort();
+    for (field, id) in ids {
+        let index = field.saturating_sub(1).min(fields.len());
+        fields.insert(index, id);
+    }
+    Ok(fields.join(&log.delimiter))
+}
+
+/// `--config-toml` value making jj render the log with `log.template`.
+fn log_config_toml() -> VjjResult<String> {
+    Ok(format!(
+        "templates.log = {}",
+        toml::Value::String(get_config()?.log.template.clone())
+    ))
+}
+
+/// Runs the `preview_command` of the mode for `rev`.
+fn command_preview(mode: &Mode, rev: &str, pager: bool, interactive: bool) -> VjjResult<()> {
+    let rev = rev.trim_matches('\'');
+    let config = get_config()?;
+    let Some(command) = config
+        .modes
+        .get(mode.keymap())
+        .and_then(|mode_config| mode_config.preview_command.as_ref())
+    else {
+        return Ok(());
+    };
+    if rev.is_empty() {
+        return Ok(());
+    }
+    let args = ["--ignore-working-copy".to_string()]
+        .into_iter()
+        .chain(
+            command
+                .iter()
+                .map(|arg| mode.expand(arg).replace("{commit:focused}", rev)),
+        )
+        .collect_vec();
+    jj(args, pager, interactive)?;
+    Ok(())
+}
+
+fn preview(tab: PreviewTab, rev: &str, pager: bool, interactive: bool) -> VjjResult<()> {
+    let rev = rev.trim_matches('\'');
+    if rev.is_empty() {
+        return Ok(());
+    }
+    // `jj resolve --list` fails on revisions without conflicts, show an empty state instead.
+    if tab == PreviewTab::Conflicts
+        && properties(rev).is_ok_and(|properties| !properties.contains(&Property::Conflict))
+    {
+        println!("{}", Color::Fixed(8).paint("The revision has no conflicts"));
+        return Ok(());
+    }
+    let cache = match pager || interactive {
+        true => None,
+        false => PreviewCache::open(),
+    };
+    let Some((args, cache_kind)) = tab.jj_args(rev, cache.is_some()) else {
+        return Ok(());
+    };
+    let Some((cache, kind)) = cache.zip(cache_kind) else {
+        jj(args, pager, interactive)?;
+        return Ok(());
+    };
+
+    if let Some(header) = tab.header_args(rev) {
+        let output = jj_output(&header)?;
+        io::stdout().write_all(&[output.stdout, output.stderr].concat())?;
+    }
+    let output = match cache.get(rev, &kind) {
+        Some(output) => output,
+        None => {
+            let output = jj_output(&args)?;
+            if output.status.success() {
+                cache.put(rev, &kind, &output.stdout)?;
+            }
+            [output.stdout, output.stderr].concat()
+        }
+    };
+    io::stdout().write_all(&output)?;
+    Ok(())
+}
+
+/// Fills the preview cache for the parents and children of `rev` with the active tab.
+fn prefetch(mode: &Mode, rev: &str) -> VjjResult<()> {
+    let Some(cache) = PreviewCache::open() else {
+        return Ok(());
+    };
+    let Some(_lock) = cache.lock_prefetch() else {
+        return Ok(());
+    };
+    let config = get_config()?;
+    let output = jj_plain_output([
+        "--ignore-working-copy".to_string(),
+        "--config-toml".to_string(),
+        log_config_toml()?,
+        "log".to_string(),
+        "--no-graph".to_string(),
+        "-r".to_string(),
+        format!("parents({rev}) | children({rev})"),
+    ])?;
+    let tab = PreviewTab::current(mode);
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let Some(commit) = line
+            .split(config.log.delimiter.as_str())
+            .nth(config.log.commit_field.saturating_sub(1))
+            .filter(|commit| !commit.is_empty())
+        else {
+            continue;
+        };
+        let Some((args, Some(kind))) = tab.jj_args(commit, true) else {
+            return Ok(());
+        };
+        if cache.get(commit, &kind).is_none() {
+            let output = jj_output(&args)?;
+            if output.status.success() {
+                cache.put(commit, &kind, &output.stdout)?;
+            }
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub enum VjjCommand {
     Log,
-    Help,
+    /// Lists the bindings of the current mode, greying out the given keys that don't apply.
+    Help(Vec<String>),
     Show(String),
+    DiffStat(String),
+    Evolution(String),
+    Descendants(String),
+    Conflicts(String),
+    HistoryOutput(String),
+    ConflictMarkers(String),
+    DescribeMany {
+        revs: Vec<String>,
+        ignore_immutable: bool,
+    },
+    InsertPreview {
+        target: String,
+        before: bool,
+    },
+    Simulate(Vec<String>),
+    PendingDryRun,
+    WorkspaceShow(String),
+    Guarded,
+    /// Help and actions of a binding of the current mode.
+    Binding(String),
+    /// Output of the `preview_command` of the mode for a revision.
+    CommandPreview(String),
+    PushDryRun {
+        remote: String,
+        change: bool,
+    },
+    BranchPushDryRun(String),
+    FileDiff(String),
+    Prefetch(String),
     Jujutsu(Vec<String>),
+    Job(Vec<String>),
+    JobOutput,
     Output(String),
     Error(String),
+    Pick {
+        format: PickFormat,
+        change: Vec<String>,
+        commit: Vec<String>,
+    },
 }
 
 impl Display for VjjCommand {
@@ -143,3 +490,36 @@ impl FromStr for VjjCommand {
         ron::from_str(s)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const D: &str = "\u{200B}";
+
+    #[test]
+    fn move_id_fields_moves_ids_to_the_log_fields() {
+        let line = ["○  ", "desc", "change", "commit", " rest"].join(D);
+        assert_eq!(
+            move_id_fields(&line, (3, 4)).unwrap(),
+            ["○  ", "change", "commit", "desc", " rest"].join(D)
+        );
+        let line = ["○  ", "commit", "change", " rest"].join(D);
+        assert_eq!(
+            move_id_fields(&line, (3, 2)).unwrap(),
+            ["○  ", "change", "commit", " rest"].join(D)
+        );
+    }
+
+    #[test]
+    fn move_id_fields_leaves_other_lines_alone() {
+        let line = ["○  ", "change", "commit", " rest"].join(D);
+        assert_eq!(move_id_fields(&line, (2, 3)).unwrap(), line);
+        assert_eq!(
+            move_id_fields("│  description", (3, 4)).unwrap(),
+            "│  description"
+        );
+        assert_eq!(move_id_fields(&line, (3, 3)).unwrap(), line);
+        assert_eq!(move_id_fields(&line, (0, 3)).unwrap(), line);
+    }
+}
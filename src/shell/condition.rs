@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use super::exec::jj_plain_output;
+use super::focus::log_ids;
+use super::immutable::immutable_revisions;
+use crate::common::{VjjError, VjjResult};
+use crate::session::Session;
+
+/// File in the session directory with the properties of the revisions of the log.
+const LOG_PROPERTIES: &str = "log-properties";
+
+/// Properties of a revision, rendered by jj into one line per revision.
+const PROPERTIES_TEMPLATE: &str = r#"change_id ++ " " ++ separate(" ", if(conflict, "conflict"), if(empty, "empty"), if(branches, "has_branch"), if(current_working_copy, "working_copy")) ++ "\n""#;
+
+/// Condition on the focused revision for a binding to apply, like `not(immutable)`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum When {
+    Conflict,
+    Immutable,
+    Empty,
+    HasBranch,
+    WorkingCopy,
+    /// The revision is in the revset, which can use the values of the keymap like `{mark:a}`.
+    Revset(String),
+    Not(Box<When>),
+}
+
+impl When {
+    fn phrase(&self, negated: bool) -> String {
+        let (phrase, negated_phrase) = match self {
+            When::Conflict => ("has conflicts", "has no conflicts"),
+            When::Immutable => ("is immutable", "is mutable"),
+            When::Empty => ("is empty", "is not empty"),
+            When::HasBranch => ("has a branch", "has no branch"),
+            When::WorkingCopy => ("is the working copy", "is not the working copy"),
+            When::Revset(revset) => {
+                return match negated {
+                    true => format!("is not in `{revset}`"),
+                    false => format!("is in `{revset}`"),
+                }
+            }
+            When::Not(when) => return when.phrase(!negated),
+        };
+        match negated {
+            true => negated_phrase.to_string(),
+            false => phrase.to_string(),
+        }
+    }
+}
+
+/// Describes the revisions the condition holds for, e.g. "is mutable" for `not(immutable)`.
+impl Display for When {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.phrase(false))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Property {
+    Conflict,
+    Immutable,
+    Empty,
+    HasBranch,
+    WorkingCopy,
+}
+
+impl Property {
+    fn parse(name: &str) -> Option<Property> {
+        match name {
+            "conflict" => Some(Property::Conflict),
+            "empty" => Some(Property::Empty),
+            "has_branch" => Some(Property::HasBranch),
+            "working_copy" => Some(Property::WorkingCopy),
+            _ => None,
+        }
+    }
+}
+
+/// The properties of `rev`, looked up in the properties of the revisions of the log when it's
+/// one of them.
+pub fn properties(rev: &str) -> VjjResult<Vec<Property>> {
+    let log_properties = log_properties()?;
+    if let Some((_, properties)) = log_properties
+        .iter()
+        .find(|(change_id, _)| change_id.starts_with(rev))
+    {
+        return Ok(properties.clone());
+    }
+    Ok(query_properties(&[rev.to_string()])?
+        .into_values()
+        .next()
+        .unwrap_or_default())
+}
+
+/// Whether `rev` is in `revset`.
+pub fn in_revset(rev: &str, revset: &str) -> VjjResult<bool> {
+    let output = jj_plain_output([
+        "--ignore-working-copy",
+        "log",
+        "--no-graph",
+        "-r",
+        &format!("({rev}) & ({revset})"),
+        "-T",
+        r#"change_id ++ "\n""#,
+    ])?;
+    if !output.status.success() {
+        return Err(VjjError::Jujutsu(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(!output.stdout.is_empty())
+}
+
+/// Forgets the properties of the revisions of the previous log, called when it's reloaded.
+pub fn clear_log_properties() {
+    if let Some(dir) = Session::dir() {
+        let _ = fs::remove_file(dir.join(LOG_PROPERTIES));
+    }
+}
+
+/// Properties of the revisions of the log by full change id, queried once per reload.
+fn log_properties() -> VjjResult<BTreeMap<String, Vec<Property>>> {
+    let path = Session::dir()
+        .ok_or(VjjError::NoSession)?
+        .join(LOG_PROPERTIES);
+    if let Some(properties) = fs::read_to_string(&path)
+        .ok()
+        .and_then(|properties| ron::from_str(&properties).ok())
+    {
+        return Ok(properties);
+    }
+    let ids = log_ids()?.into_iter().flatten().unique().collect_vec();
+    let properties = query_properties(&ids)?;
+    fs::write(path, ron::to_string(&properties).unwrap())?;
+    Ok(properties)
+}
+
+fn query_properties(revs: &[String]) -> VjjResult<BTreeMap<String, Vec<Property>>> {
+    if revs.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+    let output = jj_plain_output([
+        "--ignore-working-copy",
+        "log",
+        "--no-graph",
+        "-r",
+        revs.join("|").as_str(),
+        "-T",
+        PROPERTIES_TEMPLATE,
+    ])?;
+    if !output.status.success() {
+        return Err(VjjError::Jujutsu(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    let mut properties = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let change_id = fields.next()?.to_string();
+            Some((change_id, fields.filter_map(Property::parse).collect_vec()))
+        })
+        .collect::<BTreeMap<_, _>>();
+    for (change_id, _) in immutable_revisions(revs)? {
+        properties
+            .entry(change_id)
+            .or_default()
+            .push(Property::Immutable);
+    }
+    Ok(properties)
+}
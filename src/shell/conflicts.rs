@@ -0,0 +1,34 @@
+use std::io::{self, Write};
+
+use super::command::print_line;
+use super::exec::{jj_output, jj_plain_output};
+use crate::common::VjjResult;
+
+/// Lists the conflicted files of `rev`, identified by their path.
+pub fn print_conflict_files(rev: &str) -> VjjResult<()> {
+    let output = jj_plain_output(["resolve", "--list", "-r", rev])?;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        // Paths are padded to align the descriptions, e.g. `src/lib.rs    2-sided conflict`.
+        let (path, description) = match line.split_once("  ") {
+            Some((path, description)) => (path, description.trim()),
+            None => (line.trim(), ""),
+        };
+        print_line(
+            path,
+            &format!("{path}  {}", ansi_term::Color::Red.paint(description)),
+        )?;
+    }
+    io::stderr().write_all(&output.stderr)?;
+    Ok(())
+}
+
+/// Shows the content of a conflicted file of `rev` including the conflict markers.
+pub fn print_conflict_markers(rev: &str, path: &str) -> VjjResult<()> {
+    let path = path.trim_matches('\'');
+    if path.is_empty() {
+        return Ok(());
+    }
+    let output = jj_output(["--ignore-working-copy", "cat", "-r", rev, path])?;
+    io::stdout().write_all(&[output.stdout, output.stderr].concat())?;
+    Ok(())
+}
@@ -0,0 +1,146 @@
+use std::fs;
+
+use itertools::Itertools;
+
+use super::exec::{edit_file, jj_output, jj_plain_output};
+use super::history::HistoryEntry;
+use crate::common::{VjjError, VjjResult};
+use crate::session::Session;
+
+const SECTION_PREFIX: &str = "JJ: describe ";
+
+/// Opens the descriptions of all `revs` in a single editor buffer and describes every revision
+/// whose section was changed, with `--ignore-immutable` when the guard was told to ignore it.
+pub fn describe_many(revs: Vec<String>, ignore_immutable: bool) -> VjjResult<()> {
+    if revs.is_empty() {
+        return Err(VjjError::EmptySelection);
+    }
+    let mut descriptions = vec![];
+    for rev in revs {
+        let output = jj_plain_output([
+            "--ignore-working-copy",
+            "log",
+            "--no-graph",
+            "-r",
+            rev.as_str(),
+            "-T",
+            "description",
+        ])?;
+        let description = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        descriptions.push((rev, description));
+    }
+
+    let path = Session::dir()
+        .ok_or(VjjError::NoSession)?
+        .join("describe.jjdescription");
+    fs::write(
+        &path,
+        format!(
+            "JJ: Lines starting with \"JJ:\" are removed, keep the section headers as they are.\n{}",
+            descriptions
+                .iter()
+                .map(|(rev, description)| format!("{SECTION_PREFIX}{rev}\n{description}\n"))
+                .join("\n")
+        ),
+    )?;
+    if !edit_file(&path)?.success() {
+        return Ok(());
+    }
+
+    for (rev, description) in parse_sections(&fs::read_to_string(&path)?) {
+        let unchanged = descriptions
+            .iter()
+            .any(|(original, previous)| *original == rev && *previous == description);
+        if unchanged {
+            continue;
+        }
+        let mut args = vec!["describe".to_string(), rev, "-m".to_string(), description];
+        if ignore_immutable {
+            args.insert(0, "--ignore-immutable".to_string());
+        }
+        let output = jj_output(&args)?;
+        HistoryEntry::new(args, output.status.code(), &output.stdout, &output.stderr).record()?;
+        eprint!("{}", String::from_utf8_lossy(&output.stderr));
+    }
+    Ok(())
+}
+
+/// The description of `rev` after its first line, starting with the line break in front of it,
+/// so the quick describe can replace the first line and keep the body.
+pub fn description_body(rev: &str) -> VjjResult<String> {
+    let output = jj_plain_output([
+        "--ignore-working-copy",
+        "log",
+        "--no-graph",
+        "-r",
+        rev,
+        "-T",
+        "description",
+    ])?;
+    let description = String::from_utf8_lossy(&output.stdout);
+    Ok(match description.split_once('\n') {
+        Some((_, body)) if !body.trim().is_empty() => format!("\n{}", body.trim_end()),
+        _ => String::new(),
+    })
+}
+
+fn parse_sections(buffer: &str) -> Vec<(String, String)> {
+    let mut sections: Vec<(String, Vec<&str>)> = vec![];
+    for line in buffer.lines() {
+        if let Some(rev) = line.strip_prefix(SECTION_PREFIX) {
+            sections.push((rev.trim().to_string(), vec![]));
+        } else if line.starts_with("JJ:") {
+            continue;
+        } else if let Some((_, lines)) = sections.last_mut() {
+            lines.push(line);
+        }
+    }
+    sections
+        .into_iter()
+        .map(|(rev, lines)| (rev, lines.join("\n").trim().to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sections_in_order() {
+        let buffer = "JJ: Lines starting with \"JJ:\" are removed.\n\
+                      JJ: describe abc\n\
+                      Fix the parser\n\
+                      \n\
+                      It choked on empty input.\n\
+                      \n\
+                      JJ: describe def\n\
+                      Add a feature\n";
+        assert_eq!(
+            parse_sections(buffer),
+            vec![
+                (
+                    "abc".to_string(),
+                    "Fix the parser\n\nIt choked on empty input.".to_string()
+                ),
+                ("def".to_string(), "Add a feature".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn drops_comments_and_text_before_the_first_section() {
+        let buffer = "stray line\nJJ: describe abc\nJJ: a comment\ntitle\nJJ: describe def\n";
+        assert_eq!(
+            parse_sections(buffer),
+            vec![
+                ("abc".to_string(), "title".to_string()),
+                ("def".to_string(), String::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn no_sections_when_the_headers_were_removed() {
+        assert!(parse_sections("title\n\nbody\n").is_empty());
+    }
+}
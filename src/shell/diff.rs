@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+
+use super::exec::jj_plain_output;
+use crate::config::get_config;
+use crate::session::Session;
+
+/// Diff format used by the `jj show` preview.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffFormat {
+    /// Whatever jj is configured to use, including an external `ui.diff.tool`.
+    #[default]
+    Default,
+    Stat,
+    Summary,
+    Git,
+    ColorWords,
+    /// External diff tool configured in jj's `merge-tools` section.
+    Tool(String),
+}
+
+impl DiffFormat {
+    /// The active format of the session, defaults to the first configured format.
+    pub fn current() -> DiffFormat {
+        Session::load()
+            .diff_format
+            .or_else(|| get_config().ok()?.ui.diff_formats.first().cloned())
+            .unwrap_or_default()
+    }
+
+    /// The format following this one in `ui.diff_formats`.
+    pub fn next(&self) -> DiffFormat {
+        let formats = get_config()
+            .map(|config| config.ui.diff_formats.as_slice())
+            .unwrap_or_default();
+        formats
+            .iter()
+            .position(|format| format == self)
+            .and_then(|index| formats.get(index + 1))
+            .or(formats.first())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Name of the format in cache entries. Only formats that don't depend on the width of the
+    /// preview are cached, `default` is cached as the format jj's config resolves it to.
+    pub fn cache_kind(&self) -> Option<&'static str> {
+        match self {
+            DiffFormat::Summary => Some("summary"),
+            DiffFormat::Git => Some("git"),
+            DiffFormat::ColorWords => Some("color-words"),
+            DiffFormat::Default => DiffFormat::resolve_default().cache_kind(),
+            DiffFormat::Stat | DiffFormat::Tool(_) => None,
+        }
+    }
+
+    /// The format jj uses when no format is passed, from `ui.diff.tool` and `ui.diff.format`.
+    /// `default` when jj's config names a format vjj doesn't know. jj is only asked once per
+    /// session.
+    fn resolve_default() -> DiffFormat {
+        if let Some(format) = Session::load().default_diff_format {
+            return format;
+        }
+        let config = |name: &str| {
+            jj_plain_output(["--ignore-working-copy", "config", "get", name])
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        };
+        let format = match config("ui.diff.tool") {
+            Some(tool) => DiffFormat::Tool(tool),
+            // jj renders color words when the format isn't configured.
+            None => match config("ui.diff.format").as_deref() {
+                None | Some("color-words") => DiffFormat::ColorWords,
+                Some("git") => DiffFormat::Git,
+                Some("summary") => DiffFormat::Summary,
+                Some("stat") => DiffFormat::Stat,
+                Some(_) => DiffFormat::Default,
+            },
+        };
+        let _ = Session::update(|session| session.default_diff_format = Some(format.clone()));
+        format
+    }
+
+    pub fn args(&self) -> Vec<String> {
+        match self {
+            DiffFormat::Default => vec![],
+            DiffFormat::Stat => vec!["--stat".to_string()],
+            DiffFormat::Summary => vec!["--summary".to_string()],
+            DiffFormat::Git => vec!["--git".to_string()],
+            DiffFormat::ColorWords => vec!["--color-words".to_string()],
+            DiffFormat::Tool(tool) => vec![format!("--tool={tool}")],
+        }
+    }
+}
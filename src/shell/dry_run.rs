@@ -0,0 +1,287 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::command::VjjCommand;
+use super::exec::jj_plain_output;
+use super::VjjShellExpression;
+use crate::common::{VjjError, VjjResult};
+use crate::config::{get_config, repo_dir, workspace_jj_dir};
+use crate::session::Session;
+
+const POLL: Duration = Duration::from_millis(50);
+const TIMEOUT: Duration = Duration::from_secs(60);
+/// How long a simulation waits before copying the repo, the focus may move on in the meantime.
+const DEBOUNCE: Duration = Duration::from_millis(150);
+const COPY_PREFIX: &str = "vjj-dry-run-";
+
+/// `jj rebase` arguments moving `rev` right before or after `target`.
+pub fn insert_args(rev: &str, target: &str, before: bool) -> Vec<String> {
+    let position = match before {
+        true => "--insert-before",
+        false => "--insert-after",
+    };
+    ["rebase", "-r", rev, position, target.trim_matches('\'')]
+        .map(str::to_string)
+        .to_vec()
+}
+
+/// Prints the log as it would look after running `jj args`, without keeping the change.
+///
+/// The operation is simulated in a detached process, fzf kills preview commands when the focus
+/// moves and the copy of the repo the simulation runs in must still be removed.
+pub fn print_dry_run(args: Vec<String>) -> VjjResult<()> {
+    let path = output_path(&args)?;
+    if !path.exists() {
+        fs::write(wanted_path()?, path.to_string_lossy().as_bytes())?;
+        Command::new(std::env::current_exe()?)
+            .arg("-c")
+            .arg(VjjShellExpression::Command(VjjCommand::Simulate(args)).to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .process_group(0)
+            .spawn()?;
+        let started = Instant::now();
+        while !path.exists() && started.elapsed() < TIMEOUT {
+            thread::sleep(POLL);
+        }
+    }
+    io::stdout().write_all(&fs::read(path).unwrap_or_default())?;
+    Ok(())
+}
+
+/// Runs `jj args` in a throwaway copy of the repo and renders the log there. The repo itself is
+/// never changed, so jobs, other jj commands and `jj undo` don't notice the simulation.
+pub fn simulate(args: Vec<String>) -> VjjResult<()> {
+    let path = output_path(&args)?;
+    thread::sleep(DEBOUNCE);
+    let _lock = SimulationLock::acquire()?;
+    // Nobody waits for the result once the preview moved on to another simulation.
+    let wanted = fs::read(wanted_path()?).unwrap_or_default();
+    if path.exists() || wanted != path.to_string_lossy().as_bytes() {
+        return Ok(());
+    }
+    let copy = RepoCopy::create()?;
+    let repository = copy.0.to_string_lossy().to_string();
+
+    // Commands like `squash` may ask for a description, keep whatever jj would prefill.
+    let result = jj_plain_output(
+        [
+            "-R",
+            &repository,
+            "--ignore-working-copy",
+            "--config-toml",
+            "ui.editor = \"true\"",
+        ]
+        .map(str::to_string)
+        .into_iter()
+        .chain(args.iter().cloned()),
+    )?;
+    let log = match result.status.success() {
+        true => {
+            let mut log_args = ["-R", &repository, "--ignore-working-copy", "log"]
+                .map(str::to_string)
+                .to_vec();
+            if let Some(revset) = Session::load().revset.or(get_config()?.log.revset.clone()) {
+                log_args.extend(["-r".to_string(), revset]);
+            }
+            let log = Command::new("jj")
+                .arg("--color=always")
+                .arg("--no-pager")
+                .args(log_args)
+                .stdin(Stdio::null())
+                .output()?;
+            [log.stdout, log.stderr].concat()
+        }
+        false => result.stderr.clone(),
+    };
+    drop(copy);
+
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, [result.stderr, log].concat())?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+fn current_operation() -> VjjResult<String> {
+    let output = jj_plain_output([
+        "--ignore-working-copy",
+        "op",
+        "log",
+        "--no-graph",
+        "--limit",
+        "1",
+        "-T",
+        "id",
+    ])?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Results are kept per operation, so they are reused until the repo changes.
+fn output_path(args: &[String]) -> VjjResult<PathBuf> {
+    let mut hasher = DefaultHasher::new();
+    (current_operation()?, args).hash(&mut hasher);
+    Ok(Session::dir()
+        .ok_or(VjjError::NoSession)?
+        .join(format!("dry-run-{:016x}.log", hasher.finish())))
+}
+
+/// Output path of the simulation the preview is waiting for.
+fn wanted_path() -> VjjResult<PathBuf> {
+    Ok(Session::dir()
+        .ok_or(VjjError::NoSession)?
+        .join("dry-run.wanted"))
+}
+
+/// Removes the copies of the repo left behind by sessions that are gone, e.g. because they were
+/// killed in the middle of a simulation.
+pub fn remove_stale_copies() {
+    let Some(jj_dir) = workspace_jj_dir() else {
+        return;
+    };
+    let Ok(entries) = fs::read_dir(&jj_dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if let Some(session) = name.strip_prefix(COPY_PREFIX) {
+            if !std::env::temp_dir().join(session).exists() {
+                let _ = fs::remove_dir_all(entry.path());
+            }
+        }
+    }
+}
+
+/// Copy of the repo and the state of the workspace, removed again when dropped.
+///
+/// Files are hard linked where possible, jj and git replace their files instead of writing to
+/// them. The git objects are borrowed through `objects/info/alternates`, objects created by the
+/// simulation stay in the copy.
+struct RepoCopy(PathBuf);
+
+impl RepoCopy {
+    fn create() -> VjjResult<RepoCopy> {
+        let jj_dir = workspace_jj_dir().ok_or(VjjError::NoRepo)?;
+        let repo_dir = repo_dir().ok_or(VjjError::NoRepo)?;
+        let session = Session::dir().ok_or(VjjError::NoSession)?;
+        // Inside `.jj` so the files can be linked, jj ignores what it doesn't know there.
+        let copy = RepoCopy(jj_dir.join(format!(
+            "{COPY_PREFIX}{}",
+            session.file_name().unwrap_or_default().to_string_lossy()
+        )));
+        let _ = fs::remove_dir_all(&copy.0);
+        let copy_jj_dir = copy.0.join(".jj");
+
+        let store = repo_dir.join("store");
+        let git_dir = fs::read_to_string(store.join("git_target"))
+            .ok()
+            .and_then(|target| store.join(target.trim()).canonicalize().ok());
+        link_tree(
+            &jj_dir.join("working_copy"),
+            &copy_jj_dir.join("working_copy"),
+            None,
+        )?;
+        link_tree(&repo_dir, &copy_jj_dir.join("repo"), git_dir.as_deref())?;
+
+        if let Some(git_dir) = git_dir {
+            let copy_store = copy_jj_dir.join("repo").join("store");
+            let copy_git_dir = copy_store.join("git");
+            fs::create_dir_all(copy_git_dir.join("objects").join("info"))?;
+            // jj exports its branches to git after the operation and warns about every ref that
+            // isn't where it last saw it, so the refs have to match the ones of the repo.
+            match git_dir.join("refs").is_dir() {
+                true => link_tree(&git_dir.join("refs"), &copy_git_dir.join("refs"), None)?,
+                false => fs::create_dir_all(copy_git_dir.join("refs"))?,
+            }
+            if git_dir.join("packed-refs").exists() {
+                fs::copy(
+                    git_dir.join("packed-refs"),
+                    copy_git_dir.join("packed-refs"),
+                )?;
+            }
+            match git_dir.join("HEAD").exists() {
+                true => fs::copy(git_dir.join("HEAD"), copy_git_dir.join("HEAD")).map(drop)?,
+                false => fs::write(copy_git_dir.join("HEAD"), "ref: refs/heads/main\n")?,
+            }
+            fs::write(
+                copy_git_dir.join("config"),
+                "[core]\n\trepositoryformatversion = 0\n\tbare = true\n",
+            )?;
+            fs::write(
+                copy_git_dir.join("objects").join("info").join("alternates"),
+                format!("{}\n", git_dir.join("objects").display()),
+            )?;
+            // The linked file is shared with the repo, replace it instead of writing to it.
+            fs::remove_file(copy_store.join("git_target"))?;
+            fs::write(copy_store.join("git_target"), "git")?;
+        }
+        Ok(copy)
+    }
+}
+
+impl Drop for RepoCopy {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Recreates `from` at `to` with hard links, copying files that can't be linked. Lock files and
+/// the directory `skip` are left out.
+fn link_tree(from: &Path, to: &Path, skip: Option<&Path>) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let (path, target) = (entry.path(), to.join(entry.file_name()));
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if skip.is_none_or(|skip| path.canonicalize().is_ok_and(|path| path != skip)) {
+                link_tree(&path, &target, skip)?;
+            }
+        } else if file_type.is_file()
+            && entry.file_name() != "lock"
+            && !entry.file_name().to_string_lossy().ends_with(".lock")
+            && fs::hard_link(&path, &target).is_err()
+        {
+            fs::copy(&path, &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Only one simulation of the session runs at a time, they share the copy of the repo.
+struct SimulationLock(PathBuf);
+
+impl SimulationLock {
+    fn acquire() -> VjjResult<SimulationLock> {
+        let path = Session::dir()
+            .ok_or(VjjError::NoSession)?
+            .join("dry-run.lock");
+        let started = Instant::now();
+        while File::options()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .is_err()
+        {
+            if started.elapsed() > TIMEOUT {
+                // The previous simulation must have died, take over its lock.
+                break;
+            }
+            thread::sleep(POLL);
+        }
+        Ok(SimulationLock(path))
+    }
+}
+
+impl Drop for SimulationLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
@@ -1,33 +1,11 @@
 use std::ffi::OsStr;
 use std::fs::{self, File};
-use std::io::{self, Write};
-use std::process::{Child, ChildStdin, Command, Output, Stdio};
+use std::path::Path;
+use std::process::{Command, ExitStatus, Output, Stdio};
 
+use super::pager::Pager;
 use crate::common::VjjResult;
 
-pub struct Pager {
-    pub child: Child,
-    pub stdin: ChildStdin,
-}
-
-pub fn get_pager() -> io::Result<Pager> {
-    let tty = fs::OpenOptions::new()
-        .read(true)
-        .write(true)
-        .open("/dev/tty")?;
-    // Can use the tty_input for keys while also reading stdin for data.
-
-    let mut child = Command::new("less")
-        .arg("-KR")
-        .arg("--tabs=4")
-        .arg("-+FX")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::from(tty))
-        .spawn()?;
-    let stdin = child.stdin.take().unwrap();
-    Ok(Pager { child, stdin })
-}
-
 fn tty_stdin() -> VjjResult<File> {
     Ok(fs::OpenOptions::new()
         .read(true)
@@ -35,7 +13,27 @@ fn tty_stdin() -> VjjResult<File> {
         .open(ctty::get_path_for_dev(ctty::get_ctty_dev()?)?)?)
 }
 
-pub fn jj<I, S>(args: I, pager: bool, interactive: bool) -> VjjResult<()>
+/// Command opening `path` in `$VISUAL`, `$EDITOR` or `vi`, the variables may contain arguments.
+pub fn editor_command(path: &Path) -> Command {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut command = Command::new("/bin/sh");
+    command
+        .arg("-c")
+        .arg(format!("{editor} \"$@\""))
+        .arg("--")
+        .arg(path);
+    command
+}
+
+/// Opens `path` in the editor on the terminal, for use while fzf is running.
+pub fn edit_file(path: &Path) -> VjjResult<ExitStatus> {
+    Ok(editor_command(path).stdin(tty_stdin()?).status()?)
+}
+
+/// Runs jj in the terminal or the pager, the output only contains what was captured for the pager.
+pub fn jj<I, S>(args: I, pager: bool, interactive: bool) -> VjjResult<Output>
 where
     I: IntoIterator<Item = S>,
     S: AsRef<OsStr>,
@@ -43,45 +41,63 @@ where
     let mut command = Command::new("jj");
     command.arg("--color=always").arg("--no-pager").args(args);
 
-    match (interactive, pager) {
-        (false, false) => command.spawn()?.wait()?,
+    let uncaptured = |status| Output {
+        status,
+        stdout: vec![],
+        stderr: vec![],
+    };
+    Ok(match (interactive, pager) {
+        (false, false) => uncaptured(command.spawn()?.wait()?),
         (true, pager) => {
             command.stdin(tty_stdin()?);
             if pager {
                 command.stderr(Stdio::piped());
                 let output = command.spawn()?.wait_with_output()?;
-                let Pager {
-                    mut child,
-                    mut stdin,
-                } = get_pager()?;
-                stdin.write_all(&output.stderr)?;
-                child.wait()?;
-                output.status
+                Pager::resolve()?.page(&output.stderr)?;
+                output
             } else {
-                command.spawn()?.wait()?
+                uncaptured(command.spawn()?.wait()?)
             }
         }
 
         (false, true) => {
             command.stdout(Stdio::piped());
             command.stderr(Stdio::piped());
-            let Output {
-                status,
-                stdout,
-                stderr,
-            } = command.output()?;
-            let Pager {
-                mut child,
-                mut stdin,
-            } = get_pager()?;
-            stdin.write_all(&stdout)?;
-            stdin.write_all(&stderr)?;
-            drop(stdin);
-            child.wait()?;
+            let output = command.output()?;
+            Pager::resolve()?.page(&[output.stdout.as_slice(), &output.stderr].concat())?;
 
-            status
+            output
         }
-    };
+    })
+}
+
+/// Runs jj with colored output and returns everything it printed.
+pub fn jj_output<I, S>(args: I) -> VjjResult<Output>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    output("always", args)
+}
 
-    Ok(())
+/// Runs jj without colors, for output that is parsed by vjj.
+pub fn jj_plain_output<I, S>(args: I) -> VjjResult<Output>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    output("never", args)
+}
+
+fn output<I, S>(color: &str, args: I) -> VjjResult<Output>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    Ok(Command::new("jj")
+        .arg(format!("--color={color}"))
+        .arg("--no-pager")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()?)
 }
@@ -0,0 +1,37 @@
+use std::io::{self, Write};
+
+use ansi_term::Color;
+
+use super::command::print_line;
+use super::exec::{jj_output, jj_plain_output};
+use crate::common::VjjResult;
+
+/// Lists the files changed in `rev`, identified by their path.
+pub fn print_files(rev: &str) -> VjjResult<()> {
+    let output = jj_plain_output(["--ignore-working-copy", "diff", "--summary", "-r", rev])?;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        // Each line is the kind of change followed by the path, e.g. `M src/main.rs`.
+        let Some((kind, path)) = line.split_once(' ') else {
+            continue;
+        };
+        let color = match kind {
+            "A" => Color::Green,
+            "D" => Color::Red,
+            _ => Color::Cyan,
+        };
+        print_line(path, &format!("{} {path}", color.paint(kind)))?;
+    }
+    io::stderr().write_all(&output.stderr)?;
+    Ok(())
+}
+
+/// Shows the changes `rev` made to the file at `path`.
+pub fn print_file_diff(rev: &str, path: &str) -> VjjResult<()> {
+    let path = path.trim_matches('\'');
+    if path.is_empty() {
+        return Ok(());
+    }
+    let output = jj_output(["--ignore-working-copy", "diff", "-r", rev, path])?;
+    io::stdout().write_all(&[output.stdout, output.stderr].concat())?;
+    Ok(())
+}
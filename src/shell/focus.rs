@@ -0,0 +1,162 @@
+use std::fs;
+
+use super::condition::clear_log_properties;
+use super::exec::jj_plain_output;
+use crate::common::{VjjError, VjjResult};
+use crate::fzf::FzfAction;
+use crate::session::Session;
+
+/// File in the session directory with the change id of each line of the log.
+const LOG_IDS: &str = "log-ids";
+
+/// Revision to focus after running `jj args`: the working copy after `new` and `edit`, the moved
+/// revision after `rebase`, otherwise `focused` if it still exists.
+pub fn focus_target(args: &[String], focused: Option<String>) -> Option<String> {
+    let mut args = args.iter().skip_while(|arg| arg.starts_with('-'));
+    match args.next().map(String::as_str) {
+        Some("new" | "edit") => Some("@".to_string()),
+        Some("rebase") => rebased_revision(args.cloned().collect()).or(focused),
+        _ => focused,
+    }
+}
+
+/// The value of the first `-r`, `-s` or `-b` flag, in the `-r=<rev>` or `-r <rev>` form.
+fn rebased_revision(args: Vec<String>) -> Option<String> {
+    const FLAGS: [&str; 6] = ["-r", "-s", "-b", "--revisions", "--source", "--branch"];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if FLAGS.contains(&arg.as_str()) {
+            return args.next();
+        }
+        if let Some((flag, rev)) = arg.split_once('=') {
+            if FLAGS.contains(&flag) {
+                return Some(rev.to_string());
+            }
+        }
+    }
+    None
+}
+
+/// Moves the cursor to `target` once the log has been reloaded.
+pub fn focus_after_reload(target: Option<String>) -> VjjResult<()> {
+    if target.is_some() {
+        Session::update(|session| session.focus = target)?;
+    }
+    Ok(())
+}
+
+/// Remembers the change id of every line of the log that was just printed, empty for lines like
+/// descriptions.
+pub fn record_log_ids(ids: &[Option<String>]) -> VjjResult<()> {
+    let ids = ids
+        .iter()
+        .map(|id| id.as_deref().unwrap_or_default())
+        .collect::<Vec<_>>();
+    clear_log_properties();
+    // Commands like `vjj --pick` may print the log outside of a session.
+    if let Some(dir) = Session::dir() {
+        fs::write(dir.join(LOG_IDS), ids.join("\n"))?;
+    }
+    Ok(())
+}
+
+/// The change ids recorded by [`record_log_ids`], one per line of the list.
+pub fn log_ids() -> VjjResult<Vec<Option<String>>> {
+    let path = Session::dir().ok_or(VjjError::NoSession)?.join(LOG_IDS);
+    let ids = fs::read_to_string(path).unwrap_or_default();
+    Ok(ids
+        .lines()
+        .map(|id| Some(id.to_string()).filter(|id| !id.is_empty()))
+        .collect())
+}
+
+/// fzf actions moving the cursor to the revision remembered by [`focus_after_reload`].
+pub fn restore_focus() -> VjjResult<Vec<FzfAction>> {
+    let Some(target) = Session::load().focus else {
+        return Ok(vec![]);
+    };
+    Session::update(|session| session.focus = None)?;
+    let output = jj_plain_output([
+        "--ignore-working-copy",
+        "log",
+        "--no-graph",
+        "-r",
+        target.as_str(),
+        "-T",
+        r#"change_id ++ "\n""#,
+    ])?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let Some(change_id) = stdout.lines().next() else {
+        return Ok(vec![]);
+    };
+    Ok(log_ids()?
+        .into_iter()
+        .position(|id| id.is_some_and(|id| change_id.starts_with(&id)))
+        // fzf positions start at 1.
+        .map(|index| vec![FzfAction::Pos(index + 1)])
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn rebased_revision_takes_the_first_revision_flag() {
+        assert_eq!(
+            rebased_revision(args(&["-r", "abc", "-d", "main"])),
+            Some("abc".to_string())
+        );
+        assert_eq!(
+            rebased_revision(args(&["-d=main", "--source=abc", "-r=def"])),
+            Some("abc".to_string())
+        );
+        assert_eq!(
+            rebased_revision(args(&["--branch", "feature", "-d", "main"])),
+            Some("feature".to_string())
+        );
+    }
+
+    #[test]
+    fn rebased_revision_ignores_other_flags() {
+        assert_eq!(rebased_revision(args(&["-d", "main"])), None);
+        assert_eq!(rebased_revision(args(&["--destination=-r"])), None);
+        assert_eq!(rebased_revision(args(&["-r"])), None);
+    }
+
+    #[test]
+    fn focus_target_follows_the_command() {
+        let focused = Some("xyz".to_string());
+        assert_eq!(
+            focus_target(&args(&["new", "abc"]), focused.clone()),
+            Some("@".to_string())
+        );
+        assert_eq!(
+            focus_target(
+                &args(&["--ignore-immutable", "edit", "abc"]),
+                focused.clone()
+            ),
+            Some("@".to_string())
+        );
+        assert_eq!(
+            focus_target(
+                &args(&["rebase", "-r", "abc", "--insert-after", "def"]),
+                focused.clone()
+            ),
+            Some("abc".to_string())
+        );
+        assert_eq!(
+            focus_target(&args(&["rebase", "-d", "main"]), focused.clone()),
+            focused
+        );
+        assert_eq!(
+            focus_target(&args(&["abandon", "abc"]), focused.clone()),
+            focused
+        );
+        assert_eq!(focus_target(&[], None), None);
+    }
+}
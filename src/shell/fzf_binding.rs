@@ -4,8 +4,11 @@ use std::str::FromStr;
 use ron::error::SpannedError;
 use serde::{Deserialize, Serialize};
 
-use super::command::VjjCommand;
+use super::cache::spawn_prefetch;
+use super::focus::restore_focus;
+use super::job::{job_tick, showing_running_job};
 use super::keymap::Key;
+use super::preview::preview_actions;
 use crate::common::Selection;
 use crate::fzf::FzfAction;
 use crate::shell::keymap::{handle_key_event, Bindable, KeyHandlerContext};
@@ -22,10 +25,15 @@ pub fn fzf_handler(handler: FzfBindHandler, ctx: ShellContext) -> Vec<FzfAction>
                 "up" => FzfAction::Up,
                 _ => FzfAction::Down,
             }],
-            false => vec![
-                FzfAction::ChangePreview(VjjCommand::Show(commit)),
-                FzfAction::ChangePreviewLabel("Preview (jj show)".to_string()),
-            ],
+            false => {
+                if ctx.mode.lists_revisions() {
+                    spawn_prefetch(&ctx.mode, commit.trim_matches('\''));
+                }
+                match showing_running_job() {
+                    true => vec![],
+                    false => preview_actions(&ctx.mode, Some(commit)),
+                }
+            }
         },
         FzfBindHandler::Input { kind, selection } => {
             match handle_key_event(KeyHandlerContext::new(
@@ -33,17 +41,23 @@ pub fn fzf_handler(handler: FzfBindHandler, ctx: ShellContext) -> Vec<FzfAction>
                     InputKind::Change => Bindable::new(ctx.query.clone()),
                     InputKind::Enter => Bindable::Key(Key::Enter),
                     InputKind::Esc => Bindable::Key(Key::Esc),
+                    InputKind::CtrlC => Bindable::Key(Key::CtrlC),
+                    InputKind::DoubleClick => Bindable::Key(Key::DoubleClick),
+                    InputKind::RightClick => Bindable::Key(Key::RightClick),
                 },
-                selection,
+                selection.read(),
                 ctx.clone(),
             )) {
-                Some(mut actions) => {
-                    actions.push(FzfAction::ClearQuery);
-                    actions
-                }
+                // Clear the query first, actions like `change_query` may fill it again.
+                Some(actions) => [FzfAction::ClearQuery].into_iter().chain(actions).collect(),
                 None => vec![],
             }
         }
+        FzfBindHandler::JobTick => job_tick(),
+        FzfBindHandler::Load => match ctx.mode.lists_revisions() {
+            true => restore_focus().unwrap_or_else(Into::into),
+            false => vec![],
+        },
     }
 }
 
@@ -58,6 +72,9 @@ pub enum FzfBindHandler {
         kind: InputKind,
         selection: Selection,
     },
+    JobTick,
+    /// The list was (re)loaded.
+    Load,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -65,6 +82,9 @@ pub enum InputKind {
     Change,
     Enter,
     Esc,
+    CtrlC,
+    DoubleClick,
+    RightClick,
 }
 
 impl FromStr for FzfBindHandler {
@@ -0,0 +1,133 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use ansi_term::Color;
+use serde::{Deserialize, Serialize};
+
+use super::command::print_line;
+use crate::common::VjjResult;
+use crate::config::get_config;
+use crate::session::Session;
+
+/// A jj command executed by vjj, kept for the session and optionally in `history.file`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HistoryEntry {
+    pub args: Vec<String>,
+    pub time: SystemTime,
+    /// `None` when jj was killed, e.g. by cancelling a job.
+    pub status: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    /// jj printed to the terminal instead of vjj, so the output above is empty.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub uncaptured: bool,
+}
+
+impl HistoryEntry {
+    pub fn new(args: Vec<String>, status: Option<i32>, stdout: &[u8], stderr: &[u8]) -> Self {
+        HistoryEntry {
+            args,
+            time: SystemTime::now(),
+            status,
+            stdout: String::from_utf8_lossy(stdout).into_owned(),
+            stderr: String::from_utf8_lossy(stderr).into_owned(),
+            uncaptured: false,
+        }
+    }
+
+    pub fn command(&self) -> String {
+        format!("jj {}", self.args.join(" "))
+    }
+
+    /// Appends the entry to the session history and the persistent history file, one RON value
+    /// per line.
+    pub fn record(&self) -> VjjResult<()> {
+        let line = format!("{}\n", ron::to_string(self).unwrap());
+        let history_file = get_config()?.history.file.as_deref().map(expand_home);
+        for path in [session_history_path(), history_file].into_iter().flatten() {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?
+                .write_all(line.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// The history of the current session, oldest first.
+    pub fn load() -> Vec<HistoryEntry> {
+        session_history_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|history| {
+                history
+                    .lines()
+                    .filter_map(|line| ron::from_str(line).ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Output shown in the preview, stderr follows stdout like in the terminal.
+    pub fn output(&self) -> String {
+        let status = match self.status {
+            Some(0) => String::new(),
+            Some(code) => format!("\nexit code {code}"),
+            None => "\nkilled".to_string(),
+        };
+        if self.uncaptured {
+            return format!("{}{status}", Color::Fixed(8).paint("output not captured"));
+        }
+        format!("{}{}{status}", self.stdout, self.stderr)
+    }
+
+    fn summary(&self) -> String {
+        let age = self.time.elapsed().unwrap_or_default().as_secs();
+        let age = match age {
+            0..=59 => format!("{age}s"),
+            60..=3599 => format!("{}m", age / 60),
+            _ => format!("{}h", age / 3600),
+        };
+        let status = match self.status {
+            Some(0) => "✓",
+            _ => "✗",
+        };
+        format!("{status} {age:>3} ago  {}", self.command())
+    }
+}
+
+fn session_history_path() -> Option<PathBuf> {
+    Some(Session::dir()?.join("history.ron"))
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(path), Some(home)) => home.join(path),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Lists the history newest first, identified by the index of the entry.
+pub fn print_history() -> VjjResult<()> {
+    for (index, entry) in HistoryEntry::load().iter().enumerate().rev() {
+        print_line(&index.to_string(), &entry.summary())?;
+    }
+    Ok(())
+}
+
+pub fn print_output(index: &str) -> VjjResult<()> {
+    let history = HistoryEntry::load();
+    if let Some(entry) = index
+        .trim_matches('\'')
+        .parse::<usize>()
+        .ok()
+        .and_then(|index| history.get(index))
+    {
+        print!("{}", entry.output());
+    }
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use ansi_term::Color;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use super::exec::jj_plain_output;
+use super::keymap::Bindable;
+use crate::common::{Mode, Selection, VjjResult};
+use crate::session::Session;
+
+/// Revisions jj refuses to rewrite without `--ignore-immutable`.
+const IMMUTABLE_REVSET: &str = "::immutable_heads()";
+
+/// A key whose actions were held back because they would rewrite immutable revisions.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Guard {
+    pub key: Bindable,
+    pub mode: Mode,
+    pub query: String,
+    pub selection: Selection,
+    /// Full change ids and summaries of the immutable revisions.
+    pub immutable: Vec<(String, String)>,
+}
+
+/// The revisions of `revs` that are immutable, as full change id and summary.
+///
+/// Invalid revsets are not reported here, jj shows the error when the action runs.
+pub fn immutable_revisions(revs: &[String]) -> VjjResult<Vec<(String, String)>> {
+    if revs.is_empty() {
+        return Ok(vec![]);
+    }
+    let revset = format!(
+        "({}) & {IMMUTABLE_REVSET}",
+        revs.iter().map(|rev| format!("({rev})")).join("|")
+    );
+    let output = jj_plain_output([
+        "--ignore-working-copy",
+        "log",
+        "--no-graph",
+        "-r",
+        revset.as_str(),
+        "-T",
+        r#"change_id ++ " " ++ change_id.shortest(8) ++ " " ++ description.first_line() ++ "\n""#,
+    ])?;
+    if !output.status.success() {
+        return Ok(vec![]);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (change_id, summary) = line.split_once(' ')?;
+            Some((change_id.to_string(), summary.to_string()))
+        })
+        .collect())
+}
+
+/// Lists the immutable revisions the held back key would rewrite.
+pub fn print_guarded() -> VjjResult<()> {
+    let Some(guard) = Session::load().guard else {
+        return Ok(());
+    };
+    println!("`{}` would rewrite these immutable revisions:\n", guard.key);
+    for (_, summary) in guard.immutable {
+        let (id, description) = summary.split_once(' ').unwrap_or((&summary, ""));
+        println!("  {} {description}", Color::Purple.paint(id));
+    }
+    Ok(())
+}
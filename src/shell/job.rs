@@ -0,0 +1,243 @@
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use super::command::VjjCommand;
+use super::fzf_binding::FzfBindHandler;
+use super::history::HistoryEntry;
+use super::VjjShellExpression;
+use crate::common::{VjjError, VjjResult};
+use crate::fzf::{post_actions, FzfAction};
+use crate::session::Session;
+
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+const TICK: Duration = Duration::from_millis(100);
+/// How long the result of a finished job stays in the border label.
+const RESULT_SHOWN: Duration = Duration::from_secs(60);
+
+/// A jj command running in the background, at most one job runs per session.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Job {
+    pub args: Vec<String>,
+    pub started: SystemTime,
+    #[serde(default)]
+    pub finished: Option<SystemTime>,
+    pub pid: Option<u32>,
+    pub status: Option<JobStatus>,
+    /// Whether the log was reloaded after the job finished.
+    #[serde(default)]
+    pub reloaded: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum JobStatus {
+    Exited(i32),
+    Cancelled,
+}
+
+impl Job {
+    pub fn is_running(&self) -> bool {
+        self.status.is_none()
+    }
+
+    pub fn command(&self) -> String {
+        format!("jj {}", self.args.join(" "))
+    }
+
+    pub fn status_label(&self) -> Option<String> {
+        let elapsed = self.started.elapsed().unwrap_or_default();
+        Some(match &self.status {
+            None => {
+                let frame =
+                    SPINNER[(elapsed.as_millis() / TICK.as_millis()) as usize % SPINNER.len()];
+                format!("{frame} {} ({}s)", self.command(), elapsed.as_secs())
+            }
+            // Only show the result for a while, the output is still available in the preview.
+            Some(_)
+                if self
+                    .finished
+                    .and_then(|finished| finished.elapsed().ok())
+                    .is_some_and(|elapsed| elapsed >= RESULT_SHOWN) =>
+            {
+                return None
+            }
+            Some(JobStatus::Exited(0)) => format!("✓ {}", self.command()),
+            Some(JobStatus::Exited(code)) => format!("✗ {} (exit code {code})", self.command()),
+            Some(JobStatus::Cancelled) => format!("✗ {} (cancelled)", self.command()),
+        })
+    }
+
+    pub fn preview_label(&self) -> String {
+        match self.is_running() {
+            true => format!("Job ({})", self.command()),
+            false => format!("Output ({})", self.command()),
+        }
+    }
+
+    fn is_previewed(&self, preview_label: &str) -> bool {
+        preview_label.ends_with(&format!("({})", self.command()))
+            && (preview_label.starts_with("Job ") || preview_label.starts_with("Output "))
+    }
+
+    fn output_path() -> Option<PathBuf> {
+        Some(Session::dir()?.join("job.log"))
+    }
+}
+
+/// Starts `jj args` in a detached process and shows its output in the preview.
+pub fn start_job(args: Vec<String>) -> VjjResult<Vec<FzfAction>> {
+    if Session::load().job.is_some_and(|job| job.is_running()) {
+        return Err(VjjError::JobRunning);
+    }
+    let job = Job {
+        args: args.clone(),
+        started: SystemTime::now(),
+        finished: None,
+        pid: None,
+        status: None,
+        reloaded: false,
+    };
+    let session = Session::update(|session| session.job = Some(job.clone()))?;
+
+    Command::new(std::env::current_exe()?)
+        .arg("-c")
+        .arg(VjjShellExpression::Command(VjjCommand::Job(args)).to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    Ok(vec![
+        FzfAction::ChangePreview(VjjCommand::JobOutput),
+        FzfAction::ChangePreviewLabel(job.preview_label()),
+        FzfAction::ChangeBorderLabel(session.border_label()),
+    ])
+}
+
+/// Body of the detached job process, keeps fzf updated until jj exits.
+pub fn run_job(args: Vec<String>) -> VjjResult<()> {
+    let output = File::create(Job::output_path().ok_or(VjjError::NoSession)?)?;
+    let mut child = Command::new("jj")
+        .arg("--color=always")
+        .arg("--no-pager")
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(output.try_clone()?)
+        .stderr(output)
+        .spawn()?;
+    // The job may have been cancelled before jj was running, there was nothing to kill then.
+    let mut cancelled = false;
+    Session::update(|session| {
+        if let Some(job) = &mut session.job {
+            cancelled = !job.is_running();
+            job.pid = Some(child.id());
+        }
+    })?;
+    if cancelled {
+        child.kill()?;
+    }
+
+    let tick = [FzfAction::Transform(FzfBindHandler::JobTick)];
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        let _ = post_actions(&tick);
+        thread::sleep(TICK);
+    };
+
+    Session::update(|session| {
+        if let Some(job) = &mut session.job {
+            if job.is_running() {
+                job.status = Some(match status.code() {
+                    Some(code) => JobStatus::Exited(code),
+                    None => JobStatus::Cancelled,
+                });
+                job.finished = Some(SystemTime::now());
+            }
+        }
+    })?;
+    let output = fs::read(Job::output_path().ok_or(VjjError::NoSession)?)?;
+    HistoryEntry::new(args, status.code(), &output, &[]).record()?;
+    post_actions(&tick)?;
+    // Nothing else updates the border label once the job is done, tick once more to clear the
+    // result. fzf may have exited by then.
+    thread::sleep(RESULT_SHOWN);
+    let _ = post_actions(&tick);
+    Ok(())
+}
+
+/// Updates the border label, the job preview and reloads the log once the job is done.
+pub fn job_tick() -> Vec<FzfAction> {
+    // Ticks keep coming until the job process exits, only the first one after the job finished
+    // reloads the log.
+    let mut reload = false;
+    let Ok(session) = Session::update(|session| {
+        if let Some(job) = session.job.as_mut().filter(|job| !job.is_running()) {
+            reload = !job.reloaded;
+            job.reloaded = true;
+        }
+    }) else {
+        return vec![];
+    };
+    let Some(job) = &session.job else {
+        return vec![];
+    };
+    let mut actions = vec![FzfAction::ChangeBorderLabel(session.border_label())];
+
+    let showing_job = job.is_previewed(&std::env::var("FZF_PREVIEW_LABEL").unwrap_or_default());
+    if showing_job {
+        actions.push(FzfAction::RefreshPreview);
+    }
+    if reload {
+        actions.push(FzfAction::Reload(VjjCommand::Log));
+        if showing_job {
+            actions.push(FzfAction::ChangePreviewLabel(job.preview_label()));
+        }
+    }
+    actions
+}
+
+/// Whether the output of a running job is shown, moving the focus shouldn't replace it then.
+pub fn showing_running_job() -> bool {
+    Session::load().job.is_some_and(|job| {
+        job.is_running()
+            && job.is_previewed(&std::env::var("FZF_PREVIEW_LABEL").unwrap_or_default())
+    })
+}
+
+/// Stops the running job, returns `None` if there is none.
+pub fn cancel_job() -> VjjResult<Option<Vec<FzfAction>>> {
+    // Marked as cancelled first, a job that hasn't started jj yet kills it once it has.
+    let mut cancelled = None;
+    let session = Session::update(|session| {
+        if let Some(job) = session.job.as_mut().filter(|job| job.is_running()) {
+            job.status = Some(JobStatus::Cancelled);
+            job.finished = Some(SystemTime::now());
+            cancelled = Some(job.pid);
+        }
+    })?;
+    let Some(pid) = cancelled else {
+        return Ok(None);
+    };
+    if let Some(pid) = pid {
+        Command::new("kill").arg(pid.to_string()).status()?;
+    }
+    Ok(Some(vec![FzfAction::ChangeBorderLabel(
+        session.border_label(),
+    )]))
+}
+
+pub fn print_job_output() -> VjjResult<()> {
+    if let Some(path) = Job::output_path() {
+        print!(
+            "{}",
+            String::from_utf8_lossy(&fs::read(path).unwrap_or_default())
+        );
+    }
+    Ok(())
+}
@@ -1,35 +1,50 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::BTreeMap;
 use std::fmt::Display;
 use std::io;
 use std::process::{Command, Stdio};
+use std::str::FromStr;
 
 use copypasta::{ClipboardContext, ClipboardProvider};
 use itertools::Itertools;
 use leon::{ParseError, RenderError, Template, Values};
-use once_cell::sync::Lazy;
-use ron::error::SpannedError;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::common::{Mode, Selection, VjjError, VjjResult};
+use crate::config::get_config;
 use crate::fzf::FzfAction;
+use crate::session::Session;
 use crate::shell::command::VjjCommand;
+use crate::shell::condition::{in_revset, properties, Property, When};
+use crate::shell::describe::description_body;
+use crate::shell::diff::DiffFormat;
+use crate::shell::focus::{focus_after_reload, focus_target};
+use crate::shell::history::HistoryEntry;
+use crate::shell::immutable::{immutable_revisions, Guard};
+use crate::shell::job::{cancel_job, start_job};
+use crate::shell::marks::{jump_to_mark, set_mark};
+use crate::shell::pick::PickFormat;
+use crate::shell::preview::{preview_actions, PreviewTab};
+use crate::shell::search::{search, search_revset};
+use crate::shell::select::{invert_selection, select_revset, strip_ansi};
+use crate::shell::workspace::Workspace;
 use crate::shell::ShellContext;
 
-// TODO: add user configurable keymap
-static KEYMAP: Lazy<Result<KeyMap, SpannedError>> =
-    Lazy::new(|| ron::from_str(include_str!("../../keymap.ron")));
-
 pub fn get_keymap(mode: &Mode) -> VjjResult<&'static BTreeMap<Bindable, Keybind>> {
-    match KEYMAP.as_ref() {
-        Ok(keymap) => match keymap.get(mode.keymap()) {
-            Some(keymap) => Ok(keymap),
-            None => Err(VjjError::MisingKeymap(mode.keymap().to_string())),
-        },
-        Err(e) => Err(e.to_owned().into()),
+    match get_config()?.keymap.get(mode.keymap()) {
+        Some(keymap) => Ok(keymap),
+        None => Err(VjjError::MisingKeymap(mode.keymap().to_string())),
     }
 }
 
+/// Whether a binding of the mode reads the query as text, the query of other modes is a key
+/// sequence.
+pub fn takes_query(mode: &Mode) -> VjjResult<bool> {
+    Ok(get_keymap(mode)?
+        .values()
+        .any(|keybind| ron::to_string(keybind).unwrap().contains("{query}")))
+}
+
 impl From<VjjError> for Vec<FzfAction> {
     fn from(value: VjjError) -> Self {
         vec![FzfAction::Execute {
@@ -41,8 +56,17 @@ impl From<VjjError> for Vec<FzfAction> {
 
 pub fn handle_key_event(ctx: KeyHandlerContext) -> Option<Vec<FzfAction>> {
     if ctx.key == Bindable::new("?") {
+        let unavailable = get_keymap(&ctx.mode)
+            .map(|keymap| {
+                keymap
+                    .iter()
+                    .filter(|(_, keybind)| !keybind.applies(Some(&ctx)))
+                    .map(|(key, _)| key.to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
         return Some(vec![FzfAction::Execute {
-            command: VjjCommand::Help,
+            command: VjjCommand::Help(unavailable),
             interactive: false,
         }]);
     }
@@ -51,11 +75,15 @@ pub fn handle_key_event(ctx: KeyHandlerContext) -> Option<Vec<FzfAction>> {
         Ok(keymap) => keymap,
         Err(e) => return Some(e.into()),
     };
-    let Some(Keybind(_, actions)) = keymap.get(&ctx.key) else {
+    let Some(Keybind(help, actions, rewrites, when)) = keymap.get(&ctx.key) else {
+        // ctrl-c is bound by vjj itself, keep quitting when the keymap doesn't bind it.
+        if ctx.key == Bindable::Key(Key::CtrlC) {
+            return Some(vec![FzfAction::Abort]);
+        }
         print!(
             "{}",
             FzfAction::ChangeHeader(
-                which_key(&ctx.mode, &ctx.query).unwrap_or(ctx.mode.header().to_string())
+                which_key(&ctx.mode, &ctx.query, Some(&ctx)).unwrap_or(ctx.mode.header())
             )
         );
         return None;
@@ -65,105 +93,372 @@ pub fn handle_key_event(ctx: KeyHandlerContext) -> Option<Vec<FzfAction>> {
         print!(
             "{}",
             FzfAction::ChangeHeader(
-                which_key(&ctx.mode, &ctx.query).unwrap_or(ctx.mode.header().to_string())
+                which_key(&ctx.mode, &ctx.query, Some(&ctx)).unwrap_or(ctx.mode.header())
             )
         );
         return None;
     }
 
     let mut fzf_actions = vec![FzfAction::ChangeHeader(
-        which_key(&ctx.mode, "").unwrap_or(ctx.mode.header().to_string()),
+        which_key(&ctx.mode, "", Some(&ctx)).unwrap_or(ctx.mode.header()),
     )];
 
-    fzf_actions.append(
-        &mut actions
-            .iter()
-            .map(|action| -> VjjResult<Vec<FzfAction>> {
-                Ok(match action {
-                    UserAction::Quit => vec![FzfAction::Abort],
-                    UserAction::ReloadLog => vec![
+    if let Some(when) = when {
+        match ctx.applies(when) {
+            Ok(true) => {}
+            Ok(false) => {
+                fzf_actions
+                    .append(&mut VjjError::NotApplicable(help.clone(), when.to_string()).into());
+                return Some(fzf_actions);
+            }
+            Err(e) => {
+                fzf_actions.append(&mut e.into());
+                return Some(fzf_actions);
+            }
+        }
+    }
+
+    fzf_actions.append(&mut match guard_immutable(&ctx, rewrites) {
+        Ok(None) => run_actions(&ctx, actions),
+        Ok(Some(guard_actions)) => guard_actions,
+        Err(e) => e.into(),
+    });
+    Some(fzf_actions)
+}
+
+fn run_actions(ctx: &KeyHandlerContext, actions: &[UserAction]) -> Vec<FzfAction> {
+    actions
+        .iter()
+        .map(|action| -> VjjResult<Vec<FzfAction>> {
+            Ok(match action {
+                UserAction::Quit => vec![FzfAction::Abort],
+                UserAction::ReloadLog => {
+                    ctx.focus_after(&[])?;
+                    vec![
                         FzfAction::Reload(VjjCommand::Log),
                         FzfAction::ChangeHeader(
-                            which_key(&ctx.mode, &ctx.query)
-                                .unwrap_or(ctx.mode.header().to_string()),
+                            which_key(&ctx.mode, &ctx.query, Some(ctx))
+                                .unwrap_or(ctx.mode.header()),
                         ),
-                    ],
-                    UserAction::Mode(mode) => {
-                        let mode = mode.render_and_eval(&ctx)?;
-                        vec![
-                            FzfAction::ChangePrompt(mode.clone()),
-                            FzfAction::ChangeHeader(
-                                which_key(&mode, &ctx.query).unwrap_or(mode.header().to_string()),
-                            ),
-                            FzfAction::Reload(VjjCommand::Log),
-                        ]
-                    }
-                    UserAction::Jujutsu(args) => {
-                        let mut command = Command::new("jj");
-                        let args = args.render(&ctx)?;
-                        command
-                            .arg("--color=always")
-                            .arg("--no-pager")
-                            .args(&args)
-                            .stdout(Stdio::piped())
-                            .stderr(Stdio::piped());
-                        let output = command.output()?;
-
-                        vec![
-                            FzfAction::Reload(VjjCommand::Log),
-                            FzfAction::ChangePreview(VjjCommand::Output(format!(
-                                "{}{}",
-                                String::from_utf8_lossy(&output.stdout),
-                                String::from_utf8_lossy(&output.stderr)
-                            ))),
-                            FzfAction::ChangePreviewLabel(format!(
-                                "Output (jj {})",
-                                args.join(" ")
-                            )),
-                        ]
-                    }
-                    UserAction::JujutsuPaged(args) => {
-                        vec![
-                            FzfAction::Execute {
-                                command: VjjCommand::Jujutsu(args.render(&ctx)?),
-                                interactive: false,
-                            },
-                            FzfAction::Reload(VjjCommand::Log),
-                        ]
+                    ]
+                }
+                UserAction::Mode(mode) => {
+                    let mode = mode.render_and_eval(ctx)?;
+                    vec![
+                        FzfAction::ChangePrompt(mode.clone()),
+                        FzfAction::ChangeHeader(
+                            which_key(&mode, &ctx.query, None).unwrap_or(mode.header()),
+                        ),
+                        FzfAction::Reload(VjjCommand::Log),
+                    ]
+                }
+                UserAction::Jujutsu(args) => {
+                    let args = ctx.jj_args(args)?;
+                    ctx.focus_after(&args)?;
+                    run_jj(args)?
+                }
+                UserAction::JujutsuDryRun(args) => {
+                    let args = ctx.jj_args(args)?;
+                    Session::update(|session| session.pending = Some(args))?;
+                    vec![
+                        FzfAction::ChangePrompt(Mode::Confirm),
+                        FzfAction::ChangeHeader(
+                            which_key(&Mode::Confirm, "", None).unwrap_or(Mode::Confirm.header()),
+                        ),
+                        FzfAction::ChangePreview(VjjCommand::PendingDryRun),
+                        FzfAction::ChangePreviewLabel(
+                            PreviewTab::DryRun.preview_label(&Mode::Confirm),
+                        ),
+                    ]
+                }
+                UserAction::IgnoreImmutable => resume_guarded(ctx.pick, true)?,
+                UserAction::DropImmutable => resume_guarded(ctx.pick, false)?,
+                UserAction::ApplyPending => {
+                    // Taken in one update, so a repeated `enter` can't apply it twice.
+                    let mut pending = None;
+                    Session::update(|session| pending = session.pending.take())?;
+                    let pending = pending.ok_or(VjjError::NothingPending)?;
+                    ctx.focus_after(&pending)?;
+                    run_jj(pending)?
+                }
+                UserAction::DropPending => {
+                    Session::update(|session| session.pending = None)?;
+                    vec![]
+                }
+                UserAction::JujutsuBackground(args) => start_job(ctx.jj_args(args)?)?,
+                UserAction::CancelJob => cancel_job()?.unwrap_or(vec![FzfAction::Abort]),
+                UserAction::JujutsuPaged(args) => {
+                    let args = ctx.jj_args(args)?;
+                    ctx.focus_after(&args)?;
+                    vec![
+                        FzfAction::Execute {
+                            command: VjjCommand::Jujutsu(args),
+                            interactive: false,
+                        },
+                        FzfAction::Reload(VjjCommand::Log),
+                    ]
+                }
+                UserAction::JujutsuInteractive(args) => {
+                    let args = ctx.jj_args(args)?;
+                    ctx.focus_after(&args)?;
+                    vec![
+                        FzfAction::Execute {
+                            command: VjjCommand::Jujutsu(args),
+                            interactive: true,
+                        },
+                        FzfAction::Reload(VjjCommand::Log),
+                    ]
+                }
+                UserAction::Yank(command) => {
+                    ClipboardContext::new()?.set_contents(command.render(ctx)?.evaluate()?)?;
+                    vec![]
+                }
+                UserAction::ChangeRevset(command) => {
+                    change_revset(command.render(ctx)?.evaluate()?)?
+                }
+                UserAction::TargetWorkspace(command) => {
+                    let name = command.render(ctx)?.evaluate()?;
+                    let target = Some(name).filter(|name| {
+                        !name.is_empty() && Some(name) != Workspace::current().as_ref()
+                    });
+                    let session = Session::update(|session| session.workspace = target)?;
+                    vec![FzfAction::ChangeBorderLabel(session.border_label())]
+                }
+                UserAction::LastOutput => {
+                    let history = HistoryEntry::load();
+                    let last = history.last().ok_or(VjjError::EmptyHistory)?;
+                    vec![
+                        FzfAction::ChangePreview(VjjCommand::HistoryOutput(
+                            (history.len() - 1).to_string(),
+                        )),
+                        FzfAction::ChangePreviewLabel(format!("Output ({})", last.command())),
+                    ]
+                }
+                UserAction::ChangeQuery(command) => {
+                    vec![FzfAction::ChangeQuery(command.render(ctx)?.evaluate()?)]
+                }
+                UserAction::Prompt(mode) => {
+                    let mode = mode.render_and_eval(ctx)?;
+                    vec![
+                        FzfAction::ChangePrompt(mode.clone()),
+                        FzfAction::ChangeHeader(
+                            which_key(&mode, "", None).unwrap_or(mode.header()),
+                        ),
+                    ]
+                }
+                UserAction::SelectRevset(command) => select_revset(
+                    &command.render(ctx)?.evaluate()?,
+                    ctx.selection.change().focused.as_deref(),
+                )?,
+                UserAction::InvertSelection => match ctx.mode.lists_revisions() {
+                    true => invert_selection(ctx.selection.change().focused.as_deref())?,
+                    false => vec![FzfAction::ToggleAll],
+                },
+                UserAction::ClearSelection => vec![FzfAction::DeselectAll],
+                UserAction::SetMark(command) => {
+                    let focused = ctx
+                        .selection
+                        .change()
+                        .focused
+                        .ok_or(VjjError::EmptySelection)?;
+                    set_mark(&command.render(ctx)?.evaluate()?, &focused)?;
+                    ctx.focus_after(&[])?;
+                    vec![FzfAction::Reload(VjjCommand::Log)]
+                }
+                UserAction::JumpToMark(command) => jump_to_mark(&command.render(ctx)?.evaluate()?)?,
+                UserAction::Search(command) => {
+                    let text = command.render(ctx)?.evaluate()?;
+                    Session::update(|session| {
+                        session.search = Some(text.clone()).filter(|text| !text.is_empty())
+                    })?;
+                    match text.is_empty() {
+                        true => vec![],
+                        false => search(&text, ctx.selection.change().focused.as_deref(), false)?,
                     }
-                    UserAction::JujutsuInteractive(args) => {
-                        vec![
-                            FzfAction::Execute {
-                                command: VjjCommand::Jujutsu(args.render(&ctx)?),
-                                interactive: true,
+                }
+                UserAction::SearchNext | UserAction::SearchPrevious => search(
+                    &Session::load().search.ok_or(VjjError::NoSearch)?,
+                    ctx.selection.change().focused.as_deref(),
+                    matches!(action, UserAction::SearchPrevious),
+                )?,
+                UserAction::FilterSearch => {
+                    let text = Session::load().search.ok_or(VjjError::NoSearch)?;
+                    change_revset(search_revset(&text))?
+                }
+                UserAction::ClearMarks => {
+                    Session::update(|session| session.marks.clear())?;
+                    ctx.focus_after(&[])?;
+                    vec![FzfAction::Reload(VjjCommand::Log)]
+                }
+                UserAction::DescribeSelected => {
+                    ctx.focus_after(&[])?;
+                    vec![
+                        FzfAction::Execute {
+                            command: VjjCommand::DescribeMany {
+                                revs: ctx.selection.change().selected,
+                                ignore_immutable: ctx.ignore_immutable,
                             },
-                            FzfAction::Reload(VjjCommand::Log),
-                        ]
-                    }
-                    UserAction::Yank(command) => {
-                        ClipboardContext::new()?.set_contents(command.render(&ctx)?.evaluate()?)?;
-                        vec![]
-                    }
-                    UserAction::ChangeRevset(command) => vec![
-                        FzfAction::ChangeBorderLabel(command.render(&ctx)?.evaluate()?),
+                            interactive: true,
+                        },
                         FzfAction::Reload(VjjCommand::Log),
-                    ],
-                    UserAction::Accept(command) => vec![FzfAction::Become(VjjCommand::Output(
-                        command.render(&ctx)?.evaluate()?,
+                    ]
+                }
+                UserAction::CycleDiffFormat => {
+                    Session::update(|session| {
+                        session.diff_format = Some(DiffFormat::current().next())
+                    })?;
+                    preview_actions(&ctx.mode, ctx.selection.commit().focused)
+                }
+                UserAction::RunBinding(command) => {
+                    let key = Bindable::from(command.render(ctx)?.evaluate()?);
+                    handle_key_event(KeyHandlerContext {
+                        key,
+                        query: String::new(),
+                        ..ctx.clone()
+                    })
+                    .unwrap_or_default()
+                }
+                UserAction::PreviewTab(tab) => {
+                    if PreviewTab::tabs(&ctx.mode).contains(tab) {
+                        Session::update(|session| session.preview_tab = Some(*tab))?;
+                    }
+                    preview_actions(&ctx.mode, ctx.selection.commit().focused)
+                }
+                UserAction::NextPreviewTab | UserAction::PreviousPreviewTab => {
+                    let offset = match action {
+                        UserAction::PreviousPreviewTab => -1,
+                        _ => 1,
+                    };
+                    Session::update(|session| {
+                        session.preview_tab =
+                            Some(PreviewTab::current(&ctx.mode).cycle(&ctx.mode, offset))
+                    })?;
+                    preview_actions(&ctx.mode, ctx.selection.commit().focused)
+                }
+                UserAction::Accept(command) => match ctx.pick {
+                    Some(format) => vec![FzfAction::Become(VjjCommand::Pick {
+                        format,
+                        change: ctx.selection.change().selected,
+                        commit: ctx.selection.commit().selected,
+                    })],
+                    None => vec![FzfAction::Become(VjjCommand::Output(
+                        command.render(ctx)?.evaluate()?,
                     ))],
-                })
+                },
             })
-            .flat_map(|result| match result {
-                Ok(actions) => actions,
-                Err(e) => e.into(),
-            })
-            .collect_vec(),
-    );
+        })
+        .flat_map(|result| match result {
+            Ok(actions) => actions,
+            Err(e) => e.into(),
+        })
+        .collect_vec()
+}
 
-    Some(fzf_actions)
+/// Holds the actions of a key back when they would rewrite immutable revisions and asks what to
+/// do about them in the immutable mode.
+fn guard_immutable(
+    ctx: &KeyHandlerContext,
+    rewrites: &[String],
+) -> VjjResult<Option<Vec<FzfAction>>> {
+    let immutable = immutable_revisions(&ctx.rewritten(rewrites)?)?;
+    if immutable.is_empty() {
+        return Ok(None);
+    }
+    Session::update(|session| {
+        session.guard = Some(Guard {
+            key: ctx.key.clone(),
+            mode: ctx.mode.clone(),
+            query: ctx.query.clone(),
+            selection: ctx.selection.clone(),
+            immutable,
+        })
+    })?;
+    let mode = Mode::Immutable;
+    Ok(Some(vec![
+        FzfAction::ChangePrompt(mode.clone()),
+        FzfAction::ChangeHeader(which_key(&mode, "", None).unwrap_or(mode.header())),
+        FzfAction::ChangePreview(VjjCommand::Guarded),
+        FzfAction::ChangePreviewLabel(PreviewTab::Immutable.preview_label(&mode)),
+    ]))
 }
 
-pub fn which_key(mode: &Mode, query: &str) -> Option<String> {
+/// Runs the actions held back by [`guard_immutable`] in the mode they were triggered in, either
+/// with `--ignore-immutable` or without the immutable revisions in the selection.
+fn resume_guarded(pick: Option<PickFormat>, ignore: bool) -> VjjResult<Vec<FzfAction>> {
+    let guard = Session::load().guard.ok_or(VjjError::NothingGuarded)?;
+    Session::update(|session| session.guard = None)?;
+    let Some(Keybind(_, actions, rewrites, _)) = get_keymap(&guard.mode)?.get(&guard.key) else {
+        return Err(VjjError::NothingGuarded);
+    };
+    let ids = guard.immutable.into_iter().map(|(id, _)| id).collect_vec();
+    let ctx = KeyHandlerContext {
+        key: guard.key,
+        selection: match ignore {
+            true => guard.selection,
+            false => guard.selection.without(&ids),
+        },
+        mode: guard.mode,
+        query: guard.query,
+        pick,
+        ignore_immutable: ignore,
+    };
+    if !ignore {
+        // Only go on when something is left to rewrite and none of it is immutable anymore, the
+        // guard can't drop placeholders like `{workspace:wc}` from the selection.
+        match ctx.rewritten(rewrites) {
+            Ok(revs) if !revs.is_empty() && immutable_revisions(&revs)?.is_empty() => {}
+            _ => return Err(VjjError::EmptySelection),
+        }
+    }
+    let mut fzf_actions = vec![
+        FzfAction::ChangePrompt(ctx.mode.clone()),
+        FzfAction::ChangeHeader(which_key(&ctx.mode, "", Some(&ctx)).unwrap_or(ctx.mode.header())),
+    ];
+    fzf_actions.append(&mut run_actions(&ctx, actions));
+    Ok(fzf_actions)
+}
+
+/// Shows the log of `revset`, the default one when empty.
+fn change_revset(revset: String) -> VjjResult<Vec<FzfAction>> {
+    let session = Session::update(|session| {
+        session.revset = Some(revset).filter(|revset| !revset.is_empty())
+    })?;
+    Ok(vec![
+        FzfAction::ChangeBorderLabel(session.border_label()),
+        FzfAction::Reload(VjjCommand::Log),
+    ])
+}
+
+/// Runs jj and shows its output in the preview.
+fn run_jj(args: Vec<String>) -> VjjResult<Vec<FzfAction>> {
+    let output = Command::new("jj")
+        .arg("--color=always")
+        .arg("--no-pager")
+        .args(&args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()?;
+    HistoryEntry::new(
+        args.clone(),
+        output.status.code(),
+        &output.stdout,
+        &output.stderr,
+    )
+    .record()?;
+
+    Ok(vec![
+        FzfAction::Reload(VjjCommand::Log),
+        FzfAction::ChangePreview(VjjCommand::Output(format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        ))),
+        FzfAction::ChangePreviewLabel(format!("Output (jj {})", args.join(" "))),
+    ])
+}
+
+/// Lists the keys continuing `query`, greying out bindings whose condition doesn't hold in `ctx`.
+pub fn which_key(mode: &Mode, query: &str, ctx: Option<&KeyHandlerContext>) -> Option<String> {
     let keymap = get_keymap(mode).ok()?;
 
     let options = keymap
@@ -190,16 +485,16 @@ pub fn which_key(mode: &Mode, query: &str) -> Option<String> {
 
         options
             .iter()
-            .map(|(key, Keybind(help, actions))| {
-                let color = if actions.is_empty() {
-                    ansi_term::Color::Blue
-                } else {
-                    ansi_term::Color::Red
-                };
-                format!(
-                    "{}  {help}",
-                    color.paint(format!("{:width$}", key.to_string(), width = max_len)),
-                )
+            .map(|(key, keybind)| {
+                let key = format!("{:width$}", key.to_string(), width = max_len);
+                let Keybind(help, actions, ..) = keybind;
+                match (actions.is_empty(), keybind.applies(ctx)) {
+                    (true, _) => format!("{}  {help}", ansi_term::Color::Blue.paint(key)),
+                    (false, true) => format!("{}  {help}", ansi_term::Color::Red.paint(key)),
+                    (false, false) => ansi_term::Color::Fixed(8)
+                        .paint(format!("{key}  {help}"))
+                        .to_string(),
+                }
             })
             .collect_vec()
     };
@@ -207,17 +502,20 @@ pub fn which_key(mode: &Mode, query: &str) -> Option<String> {
     let left = align(left);
     let right = align(right);
 
-    let max_len = left.iter().map(|left| left.len()).max().unwrap_or(0);
+    // Pad by the visible width, the color codes differ in length.
+    let width = |text: &str| strip_ansi(text).chars().count();
+    let max_len = left.iter().map(|left| width(left)).max().unwrap_or(0);
+    let pad = |left: &str| format!("{left}{}", " ".repeat(max_len - width(left)));
 
     Some(
         left.iter()
             .zip_longest(right)
             .map(|item| match item {
                 itertools::EitherOrBoth::Both(left, right) => {
-                    format!("{left:width$} │ {right}", width = max_len)
+                    format!("{} │ {right}", pad(left))
                 }
                 itertools::EitherOrBoth::Left(left) => {
-                    format!("{left:width$} │", width = max_len)
+                    format!("{} │", pad(left))
                 }
                 itertools::EitherOrBoth::Right(right) => {
                     format!("{:width$} │ {right}", "", width = max_len)
@@ -251,12 +549,29 @@ impl VjjTemplate for String {
     }
 }
 
+/// An argument starting with `...` and ending with a value, like `"...{file:selected}"` or
+/// `"...--remote={remote:selected}"`, is expanded to one argument per line of the value.
 impl VjjTemplate for Vec<String> {
     fn render(&self, ctx: &KeyHandlerContext) -> Result<Vec<String>, TemplateError>
     where
         Self: Sized,
     {
-        self.iter().map(|str| str.render(ctx)).collect()
+        let mut args = vec![];
+        for arg in self {
+            let spread = arg
+                .strip_prefix("...")
+                .filter(|arg| arg.ends_with('}'))
+                .and_then(|arg| Some((arg, arg.rfind('{')?)));
+            match spread {
+                Some((arg, start)) => {
+                    let prefix = arg[..start].to_string().render(ctx)?;
+                    let selected = arg[start..].to_string().render(ctx)?;
+                    args.extend(selected.lines().map(|item| format!("{prefix}{item}")));
+                }
+                None => args.push(arg.render(ctx)?),
+            }
+        }
+        Ok(args)
     }
 }
 
@@ -309,9 +624,58 @@ pub enum UserAction {
     JujutsuPaged(Vec<String>),
     #[serde(rename = "jji")]
     JujutsuInteractive(Vec<String>),
+    /// Runs jj in the background, showing its progress in the border and its output in the
+    /// preview.
+    #[serde(rename = "jjb")]
+    JujutsuBackground(Vec<String>),
+    /// Shows the log as it would look after running jj and asks for confirmation first.
+    #[serde(rename = "jjd")]
+    JujutsuDryRun(Vec<String>),
+    /// Runs the command of the last `jjd` action.
+    ApplyPending,
+    /// Forgets the command of the last `jjd` action without running it.
+    DropPending,
+    /// Runs the actions held back by the immutable mode with `--ignore-immutable`.
+    IgnoreImmutable,
+    /// Runs the actions held back by the immutable mode without the immutable revisions.
+    DropImmutable,
+    /// Makes `{workspace:wc}` refer to the working-copy commit of another workspace, the current
+    /// one when empty.
+    TargetWorkspace(UserCommand),
+    CancelJob,
     Yank(UserCommand),
     ChangeRevset(UserCommand),
     Accept(UserCommand),
+    /// Shows the output of the last jj command in the preview again.
+    LastOutput,
+    /// Replaces the query, e.g. to prefill a description in the describe mode.
+    ChangeQuery(UserCommand),
+    /// Opens the descriptions of all selected revisions in a single editor buffer.
+    DescribeSelected,
+    /// Switches the mode without reloading the list, for modes listing the same lines.
+    Prompt(UserMode),
+    /// Adds the revisions of a revset to the selection.
+    SelectRevset(UserCommand),
+    InvertSelection,
+    ClearSelection,
+    /// Marks the focused revision, it's available as `{mark:<name>}` until vjj exits.
+    SetMark(UserCommand),
+    JumpToMark(UserCommand),
+    ClearMarks,
+    /// Moves the cursor to the next revision of the log matching the text, remembering it for
+    /// `search_next` and `search_previous`.
+    Search(UserCommand),
+    SearchNext,
+    SearchPrevious,
+    /// Shows the revisions whose description, author or branches contain the last searched text.
+    FilterSearch,
+    /// Runs the binding of a key of the current mode, like the focused entry of the menu mode.
+    RunBinding(UserCommand),
+    CycleDiffFormat,
+    /// Shows a tab of the preview, if the mode has it.
+    PreviewTab(PreviewTab),
+    NextPreviewTab,
+    PreviousPreviewTab,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -320,6 +684,22 @@ pub enum UserMode {
     Normal,
     Revset,
     Obslog(UserCommand),
+    Conflicts,
+    ConflictFiles(UserCommand),
+    Files(UserCommand),
+    Describe(UserCommand),
+    Move(UserCommand),
+    Workspaces,
+    AddWorkspace(UserCommand),
+    Remotes(UserCommand),
+    /// Branches of a revision and the remote to push them to.
+    Branches(UserCommand, UserCommand),
+    Select,
+    Search,
+    Menu(UserCommand),
+    History,
+    /// A mode of the config by name, with its arguments like `custom("stacks", ["{rev}"])`.
+    Custom(String, Vec<UserCommand>),
 }
 
 impl UserMode {
@@ -328,15 +708,67 @@ impl UserMode {
             UserMode::Normal => Mode::Normal,
             UserMode::Revset => Mode::Revset,
             UserMode::Obslog(command) => Mode::Obslog(command.render(ctx)?.evaluate()?),
+            UserMode::Conflicts => Mode::Conflicts,
+            UserMode::ConflictFiles(command) => {
+                Mode::ConflictFiles(command.render(ctx)?.evaluate()?)
+            }
+            UserMode::Files(command) => Mode::Files(command.render(ctx)?.evaluate()?),
+            UserMode::Describe(command) => Mode::Describe(command.render(ctx)?.evaluate()?),
+            UserMode::Move(command) => Mode::Move(command.render(ctx)?.evaluate()?),
+            UserMode::Workspaces => Mode::Workspaces,
+            UserMode::AddWorkspace(command) => Mode::AddWorkspace(command.render(ctx)?.evaluate()?),
+            UserMode::Remotes(command) => Mode::Remotes(command.render(ctx)?.evaluate()?),
+            UserMode::Branches(rev, remote) => Mode::Branches(
+                rev.render(ctx)?.evaluate()?,
+                remote.render(ctx)?.evaluate()?,
+            ),
+            UserMode::Select => Mode::Select,
+            UserMode::Search => Mode::Search,
+            UserMode::Menu(command) => Mode::Menu(command.render(ctx)?.evaluate()?),
+            UserMode::History => Mode::History,
+            UserMode::Custom(name, args) => Mode::Custom(
+                name.clone(),
+                args.iter()
+                    .map(|arg| Ok(arg.render(ctx)?.evaluate()?))
+                    .collect::<VjjResult<_>>()?,
+            ),
         })
     }
 }
 
+/// Help text, actions, the revisions the actions rewrite, like `["{change:selected_revset}"]`,
+/// which are checked for immutable ones first, and the condition the focused revision has to meet
+/// for the binding to apply, like `not(immutable)`.
 #[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct Keybind(pub String, pub Vec<UserAction>);
+pub struct Keybind(
+    pub String,
+    pub Vec<UserAction>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")] pub Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")] pub Option<When>,
+);
+
+impl Keybind {
+    /// Names of the custom modes the binding opens, they have to be defined in `modes`.
+    pub fn custom_modes(&self) -> impl Iterator<Item = &str> {
+        self.1.iter().filter_map(|action| match action {
+            UserAction::Mode(UserMode::Custom(name, _))
+            | UserAction::Prompt(UserMode::Custom(name, _)) => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Whether the condition of the binding holds in `ctx`, assuming it does when that can't be
+    /// told.
+    pub fn applies(&self, ctx: Option<&KeyHandlerContext>) -> bool {
+        match (&self.3, ctx) {
+            (Some(when), Some(ctx)) => ctx.applies(when).unwrap_or(true),
+            _ => true,
+        }
+    }
+}
 
 #[derive(Debug, Deserialize, Serialize, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
-#[serde(untagged)]
+#[serde(from = "String", into = "String")]
 pub enum Bindable {
     Key(Key),
     Sequence(String),
@@ -348,17 +780,40 @@ impl From<Key> for Bindable {
     }
 }
 
+/// Special keys are written like `<esc>`, everything else is a sequence of typed characters.
 impl From<String> for Bindable {
     fn from(value: String) -> Self {
-        Self::Sequence(value)
+        match value
+            .strip_prefix('<')
+            .and_then(|name| name.strip_suffix('>'))
+            .and_then(|name| Key::from_str(name).ok())
+        {
+            Some(key) => Self::Key(key),
+            None => Self::Sequence(value),
+        }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
-#[serde(rename_all = "snake_case")]
+impl From<Bindable> for String {
+    fn from(value: Bindable) -> Self {
+        match value {
+            Bindable::Key(key) => format!("<{key}>"),
+            Bindable::Sequence(keys) => keys,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, strum::Display, strum::EnumString)]
+#[strum(serialize_all = "snake_case")]
 pub enum Key {
     Esc,
     Enter,
+    #[strum(serialize = "ctrl-c")]
+    CtrlC,
+    #[strum(serialize = "double-click")]
+    DoubleClick,
+    #[strum(serialize = "right-click")]
+    RightClick,
 }
 
 impl Bindable {
@@ -373,15 +828,14 @@ impl Display for Bindable {
             f,
             "{}",
             match self {
-                Bindable::Key(Key::Esc) => "esc".to_string(),
-                Bindable::Key(Key::Enter) => "enter".to_string(),
+                Bindable::Key(key) => key.to_string(),
                 Bindable::Sequence(keys) => keys.replace(' ', "<space>"),
             }
         )
     }
 }
 
-type KeyMap = HashMap<String, BTreeMap<Bindable, Keybind>>;
+pub type KeyMap = BTreeMap<String, BTreeMap<Bindable, Keybind>>;
 
 #[derive(Debug, Clone)]
 pub struct KeyHandlerContext {
@@ -389,6 +843,8 @@ pub struct KeyHandlerContext {
     mode: Mode,
     query: String,
     selection: Selection,
+    pick: Option<PickFormat>,
+    ignore_immutable: bool,
 }
 
 impl KeyHandlerContext {
@@ -398,8 +854,56 @@ impl KeyHandlerContext {
             mode: shell_ctx.mode,
             query: shell_ctx.query,
             selection,
+            pick: shell_ctx.pick,
+            ignore_immutable: false,
         }
     }
+
+    /// Renders the arguments of a jj action.
+    fn jj_args(&self, args: &Vec<String>) -> Result<Vec<String>, TemplateError> {
+        let mut args = args.render(self)?;
+        if self.ignore_immutable {
+            args.insert(0, "--ignore-immutable".to_string());
+        }
+        Ok(args)
+    }
+
+    /// The focused revision, or the one of modes listing other things like the menu mode.
+    fn revision(&self) -> Option<String> {
+        match self.mode.lists_revisions() {
+            true => self.selection.change().focused,
+            false => self.mode.rev().map(str::to_string),
+        }
+    }
+
+    /// Moves the cursor to the revision the jj command `args` is about, by default the focused
+    /// one, once the log has been reloaded.
+    fn focus_after(&self, args: &[String]) -> VjjResult<()> {
+        focus_after_reload(focus_target(args, self.revision()))
+    }
+
+    /// Whether `when` holds for the focused revision, it never does without one.
+    fn applies(&self, when: &When) -> VjjResult<bool> {
+        let Some(rev) = self.revision() else {
+            return Ok(false);
+        };
+        Ok(match when {
+            When::Conflict => properties(&rev)?.contains(&Property::Conflict),
+            When::Immutable => properties(&rev)?.contains(&Property::Immutable),
+            When::Empty => properties(&rev)?.contains(&Property::Empty),
+            When::HasBranch => properties(&rev)?.contains(&Property::HasBranch),
+            When::WorkingCopy => properties(&rev)?.contains(&Property::WorkingCopy),
+            When::Revset(revset) => in_revset(&rev, &revset.render(self)?)?,
+            When::Not(when) => !self.applies(when)?,
+        })
+    }
+
+    /// The revisions rewritten by a key, rendered from its declared placeholders.
+    fn rewritten(&self, rewrites: &[String]) -> Result<Vec<String>, TemplateError> {
+        let mut revs = rewrites.to_vec().render(self)?;
+        revs.retain(|rev| !rev.is_empty());
+        Ok(revs)
+    }
 }
 
 impl Values for KeyHandlerContext {
@@ -412,7 +916,49 @@ impl Values for KeyHandlerContext {
             "commit:focused" => self.selection.commit().focused?,
             "commit:selected" => self.selection.commit().selected.join("\n"),
             "commit:selected_revset" => self.selection.commit().selected_revset(),
-            _ => return None,
+            "rev" => self.mode.rev()?.to_string(),
+            "file:focused" if self.mode.lists_files() => self.selection.commit().focused?,
+            "file:selected" if self.mode.lists_files() => {
+                self.selection.commit().selected.join("\n")
+            }
+            "workspace:focused" if self.mode == Mode::Workspaces => {
+                self.selection.commit().focused?
+            }
+            "remote:focused" if matches!(self.mode, Mode::Remotes(_)) => {
+                self.selection.commit().focused?
+            }
+            "remote:selected" if matches!(self.mode, Mode::Remotes(_)) => {
+                self.selection.commit().selected.join("\n")
+            }
+            "branch:focused" if matches!(self.mode, Mode::Branches(..)) => {
+                self.selection.commit().focused?
+            }
+            "branch:selected" if matches!(self.mode, Mode::Branches(..)) => {
+                self.selection.commit().selected.join("\n")
+            }
+            "remote:target" => match &self.mode {
+                Mode::Branches(_, remote) => remote.clone(),
+                _ => return None,
+            },
+            "binding:focused" if matches!(self.mode, Mode::Menu(_)) => {
+                self.selection.commit().focused?
+            }
+            "description:body" if matches!(self.mode, Mode::Describe(_)) => {
+                description_body(self.mode.rev()?).ok()?
+            }
+            key if key.starts_with("arg:") => match &self.mode {
+                Mode::Custom(_, args) => args
+                    .get(key["arg:".len()..].parse::<usize>().ok()?)?
+                    .clone(),
+                _ => return None,
+            },
+            "workspace:current" => Workspace::current()?,
+            "workspace:target" => Workspace::target().or_else(Workspace::current)?,
+            "workspace:wc" => Workspace::target_wc(),
+            key => Session::load()
+                .marks
+                .get(key.strip_prefix("mark:")?)?
+                .clone(),
         }))
     }
 }
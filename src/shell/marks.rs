@@ -0,0 +1,92 @@
+use std::collections::BTreeMap;
+
+use ansi_term::Color;
+use itertools::Itertools;
+
+use super::exec::jj_plain_output;
+use super::focus::log_ids;
+use crate::common::{VjjError, VjjResult};
+use crate::fzf::FzfAction;
+use crate::session::Session;
+
+/// Remembers the change of `rev` as mark `name`, by its full change id so it keeps resolving to
+/// the same change when the shortest unique prefix grows.
+pub fn set_mark(name: &str, rev: &str) -> VjjResult<()> {
+    let output = jj_plain_output([
+        "--ignore-working-copy",
+        "log",
+        "--no-graph",
+        "-r",
+        rev,
+        "-T",
+        "change_id",
+    ])?;
+    if !output.status.success() {
+        return Err(VjjError::Jujutsu(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    let change_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Session::update(|session| {
+        session.marks.insert(name.to_string(), change_id);
+    })?;
+    Ok(())
+}
+
+/// Moves the cursor to the line of mark `name`, if the log shows it.
+pub fn jump_to_mark(name: &str) -> VjjResult<Vec<FzfAction>> {
+    let change_id = Session::load()
+        .marks
+        .get(name)
+        .cloned()
+        .ok_or_else(|| VjjError::UnknownMark(name.to_string()))?;
+    Ok(log_ids()?
+        .into_iter()
+        .position(|id| id.is_some_and(|id| change_id.starts_with(&id)))
+        .map(|index| vec![FzfAction::Pos(index + 1)])
+        .unwrap_or_default())
+}
+
+/// Column in front of the log listing the marks of each revision, left out when no marks are set.
+pub struct MarkColumn {
+    marks: BTreeMap<String, String>,
+    width: usize,
+}
+
+impl MarkColumn {
+    pub fn load() -> MarkColumn {
+        let marks = Session::load().marks;
+        // Wide enough for the change with the most marks, so the lines can be printed as they come.
+        let width = marks
+            .values()
+            .unique()
+            .map(|change_id| {
+                marks
+                    .iter()
+                    .filter(|(_, id)| *id == change_id)
+                    .map(|(name, _)| name.chars().count())
+                    .sum()
+            })
+            .max()
+            .unwrap_or(0);
+        MarkColumn { marks, width }
+    }
+
+    /// The line of the log with the marks of its revision `id` in front.
+    pub fn format(&self, line: &str, id: Option<&str>) -> String {
+        if self.width == 0 {
+            return line.to_string();
+        }
+        let names = self
+            .marks
+            .iter()
+            .filter(|(_, change_id)| id.is_some_and(|id| change_id.starts_with(id)))
+            .map(|(name, _)| name.as_str())
+            .collect::<String>();
+        let padding = " ".repeat(self.width.saturating_sub(names.chars().count()));
+        match names.is_empty() {
+            true => format!("{padding} {line}"),
+            false => format!("{}{padding} {line}", Color::Yellow.bold().paint(&names)),
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use ansi_term::Color;
+
+use super::command::print_line;
+use super::keymap::{get_keymap, Bindable, KeyHandlerContext, Keybind};
+use super::ShellContext;
+use crate::common::{Mode, Selection, VjjResult};
+
+/// Lists the bindings of the menu mode running actions that apply to its revision, special keys
+/// like `<esc>` move around the menu instead.
+pub fn print_menu(ctx: ShellContext) -> VjjResult<()> {
+    let keymap = get_keymap(&ctx.mode)?;
+    let ctx = KeyHandlerContext::new(Bindable::new(""), Selection::default(), ctx);
+    let entries = keymap
+        .iter()
+        .filter_map(|(key, keybind)| match (key, keybind) {
+            (Bindable::Sequence(keys), Keybind(help, actions, ..))
+                if !actions.is_empty() && keybind.applies(Some(&ctx)) =>
+            {
+                Some((keys, help))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let width = entries
+        .iter()
+        .map(|(keys, _)| keys.chars().count())
+        .max()
+        .unwrap_or(0);
+    for (keys, help) in entries {
+        print_line(
+            keys,
+            &format!("{}  {help}", Color::Red.paint(format!("{keys:width$}"))),
+        )?;
+    }
+    Ok(())
+}
+
+/// Prints the help and the actions of the binding of `key` in `mode`.
+pub fn print_binding(mode: &Mode, key: &str) -> VjjResult<()> {
+    let key = key.trim_matches('\'');
+    let keymap = get_keymap(mode)?;
+    if let Some(Keybind(help, actions, rewrites, _)) = keymap.get(&Bindable::from(key.to_string()))
+    {
+        println!("{help}\n");
+        for action in actions {
+            println!("{}", ron::to_string(action).unwrap());
+        }
+        if !rewrites.is_empty() {
+            println!("\nrewrites {}", rewrites.join(", "));
+        }
+    }
+    Ok(())
+}
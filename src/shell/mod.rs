@@ -1,7 +1,27 @@
+pub mod cache;
 pub mod command;
+pub mod condition;
+pub mod conflicts;
+pub mod describe;
+pub mod diff;
+pub mod dry_run;
 pub mod exec;
+pub mod files;
+pub mod focus;
 pub mod fzf_binding;
+pub mod history;
+pub mod immutable;
+pub mod job;
 pub mod keymap;
+pub mod marks;
+pub mod menu;
+pub mod pager;
+pub mod pick;
+pub mod preview;
+pub mod remote;
+pub mod search;
+pub mod select;
+pub mod workspace;
 
 use std::fmt::Display;
 use std::str::FromStr;
@@ -12,21 +32,22 @@ use serde::{Deserialize, Serialize};
 
 use self::command::{vjj_command, VjjCommand};
 use self::fzf_binding::{fzf_handler, FzfBindHandler};
+use self::pick::PickFormat;
 use crate::common::Mode;
+use crate::session::Session;
 
 pub fn vjj_shell(expression: VjjShellExpression) {
     let mode: Mode = Mode::from_str(std::env::var("FZF_PROMPT").unwrap_or_default().as_str())
         .unwrap_or_default();
 
     let query = std::env::var("FZF_QUERY").unwrap_or_default();
-    let revset = std::env::var("FZF_BORDER_LABEL")
-        .ok()
-        .filter(|s| !s.is_empty());
+    let revset = Session::load().revset;
 
     let ctx = ShellContext {
         mode,
         query,
         revset,
+        pick: PickFormat::from_env(),
     };
     match expression {
         VjjShellExpression::Handler(handler) => {
@@ -38,7 +59,8 @@ pub fn vjj_shell(expression: VjjShellExpression) {
         VjjShellExpression::Command(command) => match vjj_command(command, ctx, false, false) {
             Ok(_) => (),
             Err(e) => {
-                eprintln!("{}", e)
+                eprintln!("{}", e);
+                std::process::exit(1);
             }
         },
         VjjShellExpression::PagedCommand {
@@ -83,4 +105,5 @@ pub struct ShellContext {
     mode: Mode,
     query: String,
     revset: Option<String>,
+    pick: Option<PickFormat>,
 }
@@ -0,0 +1,125 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::VjjResult;
+use crate::config::get_config;
+use crate::session::Session;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pager {
+    /// Print the output directly to the terminal and wait for a key press.
+    WaitForKey,
+    Command(Vec<String>),
+}
+
+impl Default for Pager {
+    fn default() -> Self {
+        Pager::Command(vec!["less".to_string(), "--tabs=4".to_string()])
+    }
+}
+
+impl Pager {
+    /// Resolves the pager from `ui.pager` in the vjj config, then from jj's `ui.pager` when it
+    /// was set explicitly, then from `$PAGER`, falling back to `less`. jj is only asked once per
+    /// session.
+    pub fn resolve() -> VjjResult<Pager> {
+        if let Some(pager) = &get_config()?.ui.pager {
+            return Ok(pager.clone());
+        }
+        if let Some(pager) = Session::load().pager {
+            return Ok(pager);
+        }
+        let pager = jj_pager()
+            .or_else(|| std::env::var("PAGER").ok().and_then(|pager| split(&pager)))
+            .map(Pager::Command)
+            .unwrap_or_default();
+        Session::update(|session| session.pager = Some(pager.clone()))?;
+        Ok(pager)
+    }
+
+    pub fn page(&self, content: &[u8]) -> VjjResult<()> {
+        let mut tty = open_tty()?;
+        match self {
+            Pager::WaitForKey => {
+                tty.write_all(content)?;
+                write!(
+                    tty,
+                    "\n{}",
+                    ansi_term::Color::Blue.paint("Press any key to continue")
+                )?;
+                tty.flush()?;
+                Command::new("/bin/sh")
+                    .arg("-c")
+                    .arg("stty raw -echo; dd bs=1 count=1 >/dev/null 2>&1; stty -raw echo")
+                    .stdin(tty)
+                    .status()?;
+            }
+            Pager::Command(command) => {
+                let Some((program, args)) = command.split_first() else {
+                    return Pager::WaitForKey.page(content);
+                };
+                let mut child = Command::new(program)
+                    .args(args)
+                    .args(required_args(program))
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::from(tty))
+                    .spawn()?;
+                let mut stdin = child.stdin.take().unwrap();
+                // The pager may exit before reading everything, which is fine.
+                let _ = stdin.write_all(content);
+                drop(stdin);
+                child.wait()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Flags some pagers need to keep showing the output until they are closed, vjj runs them from
+/// inside fzf so exiting early or not clearing the screen would lose the output.
+fn required_args(program: &str) -> &'static [&'static str] {
+    match Path::new(program)
+        .file_name()
+        .and_then(|name| name.to_str())
+    {
+        Some("less") => &["-KR", "-+FX"],
+        Some("delta" | "bat") => &["--paging=always"],
+        _ => &[],
+    }
+}
+
+/// jj's `ui.pager` unless it is jj's built-in default, which would always shadow `$PAGER`.
+/// `jj config list` leaves the defaults out unless `--include-defaults` is passed.
+fn jj_pager() -> Option<Vec<String>> {
+    let output = Command::new("jj")
+        .args(["--ignore-working-copy", "config", "list", "ui.pager"])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let config = toml::from_str::<toml::Table>(&String::from_utf8_lossy(&output.stdout)).ok()?;
+    match config.get("ui")?.get("pager")? {
+        toml::Value::String(pager) => split(pager),
+        pager => pager.clone().try_into().ok(),
+    }
+}
+
+fn split(pager: &str) -> Option<Vec<String>> {
+    shell_words::split(pager)
+        .ok()
+        .filter(|args| !args.is_empty())
+}
+
+fn open_tty() -> VjjResult<File> {
+    Ok(fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")?)
+}
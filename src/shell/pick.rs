@@ -0,0 +1,95 @@
+use std::process::{Command, Stdio};
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::common::{VjjError, VjjResult};
+
+/// Environment variable telling the shell commands that vjj runs as a picker.
+pub const PICK_FORMAT_ENV: &str = "VJJ_PICK_FORMAT";
+
+/// Exit code of `vjj pick` when the picker was closed without accepting.
+pub const PICK_CANCELLED: i32 = 130;
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    Deserialize,
+    Serialize,
+    clap::ValueEnum,
+    strum::Display,
+    strum::EnumString,
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum PickFormat {
+    #[default]
+    Change,
+    Commit,
+    Json,
+}
+
+impl PickFormat {
+    pub fn from_env() -> Option<Self> {
+        std::env::var(PICK_FORMAT_ENV).ok()?.parse().ok()
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct PickedRevision {
+    change_id: String,
+    commit_id: String,
+    description: String,
+    branches: Vec<String>,
+}
+
+pub fn pick_output(format: PickFormat, change: Vec<String>, commit: Vec<String>) -> VjjResult<()> {
+    if commit.is_empty() {
+        return Err(VjjError::EmptySelection);
+    }
+    match format {
+        PickFormat::Change => println!("{}", change.join("\n")),
+        PickFormat::Commit => println!("{}", commit.join("\n")),
+        PickFormat::Json => {
+            let output = Command::new("jj")
+                .args(["--ignore-working-copy", "--no-pager", "--color=never"])
+                .args(["log", "--no-graph", "-r", &commit.join("|"), "-T"])
+                .arg(
+                    r#"change_id ++ "\0" ++ commit_id ++ "\0" ++ description ++ "\0" ++ local_branches.map(|b| b.name()).join(" ") ++ "\0""#,
+                )
+                .stderr(Stdio::inherit())
+                .output()?;
+            let output = String::from_utf8_lossy(&output.stdout);
+            let revisions = output
+                .split_terminator('\0')
+                .chunks(4)
+                .into_iter()
+                .filter_map(|mut fields| {
+                    Some(PickedRevision {
+                        change_id: fields.next()?.to_string(),
+                        commit_id: fields.next()?.to_string(),
+                        description: fields.next()?.trim_end().to_string(),
+                        branches: fields
+                            .next()?
+                            .split_whitespace()
+                            .map(String::from)
+                            .collect(),
+                    })
+                })
+                .collect_vec();
+            // jj lists the revisions in log order, they are printed in the order they were
+            // selected in.
+            let revisions = commit
+                .iter()
+                .filter_map(|prefix| {
+                    revisions
+                        .iter()
+                        .find(|revision| revision.commit_id.starts_with(prefix.as_str()))
+                })
+                .collect_vec();
+            println!("{}", serde_json::to_string_pretty(&revisions)?);
+        }
+    }
+    Ok(())
+}
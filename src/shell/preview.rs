@@ -0,0 +1,239 @@
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use super::command::VjjCommand;
+use super::diff::DiffFormat;
+use crate::common::Mode;
+use crate::config::get_config;
+use crate::fzf::FzfAction;
+use crate::session::Session;
+
+/// A tab of the preview window, the tabs of each mode are configured in `modes.<mode>.preview`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewTab {
+    #[default]
+    Show,
+    DiffStat,
+    Obslog,
+    Descendants,
+    Conflicts,
+    /// Output of the focused entry of the command history.
+    Output,
+    /// Focused conflicted file including its conflict markers.
+    ConflictMarkers,
+    /// Changes to the focused file.
+    FileDiff,
+    /// Log after moving the revision of the move mode after the focused one.
+    InsertAfter,
+    /// Log after moving the revision of the move mode before the focused one.
+    InsertBefore,
+    /// Log after running the command waiting for confirmation.
+    DryRun,
+    /// Working-copy commit of the focused workspace.
+    Workspace,
+    /// What pushing the branches of the revisions of the remotes mode to the focused remote
+    /// would do.
+    PushDryRun,
+    /// What pushing the changes of the revisions of the remotes mode would do.
+    PushChangeDryRun,
+    /// What pushing the focused branch of the branches mode to its remote would do.
+    BranchPushDryRun,
+    /// Immutable revisions a key would rewrite.
+    Immutable,
+    /// What the focused entry of the menu mode runs.
+    Binding,
+    /// Output of the `preview_command` of the mode.
+    Command,
+}
+
+impl PreviewTab {
+    pub fn tabs(mode: &Mode) -> &'static [PreviewTab] {
+        get_config()
+            .ok()
+            .and_then(|config| config.modes.get(mode.keymap()))
+            .map(|mode| mode.preview.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The active tab of the session, defaults to the first tab of the mode.
+    pub fn current(mode: &Mode) -> PreviewTab {
+        let tabs = Self::tabs(mode);
+        Session::load()
+            .preview_tab
+            .filter(|tab| tabs.contains(tab))
+            .or(tabs.first().copied())
+            .unwrap_or_default()
+    }
+
+    /// The tab `offset` tabs away from this one, wrapping around at both ends.
+    pub fn cycle(&self, mode: &Mode, offset: isize) -> PreviewTab {
+        let tabs = Self::tabs(mode);
+        if tabs.is_empty() {
+            return *self;
+        }
+        let index = tabs.iter().position(|tab| tab == self).unwrap_or(0) as isize;
+        tabs[(index + offset).rem_euclid(tabs.len() as isize) as usize]
+    }
+
+    pub fn command(&self, rev: String) -> VjjCommand {
+        match self {
+            PreviewTab::Show => VjjCommand::Show(rev),
+            PreviewTab::DiffStat => VjjCommand::DiffStat(rev),
+            PreviewTab::Obslog => VjjCommand::Evolution(rev),
+            PreviewTab::Descendants => VjjCommand::Descendants(rev),
+            PreviewTab::Conflicts => VjjCommand::Conflicts(rev),
+            PreviewTab::Output => VjjCommand::HistoryOutput(rev),
+            PreviewTab::ConflictMarkers => VjjCommand::ConflictMarkers(rev),
+            PreviewTab::FileDiff => VjjCommand::FileDiff(rev),
+            PreviewTab::InsertAfter => VjjCommand::InsertPreview {
+                target: rev,
+                before: false,
+            },
+            PreviewTab::InsertBefore => VjjCommand::InsertPreview {
+                target: rev,
+                before: true,
+            },
+            PreviewTab::DryRun => VjjCommand::PendingDryRun,
+            PreviewTab::Workspace => VjjCommand::WorkspaceShow(rev),
+            PreviewTab::PushDryRun => VjjCommand::PushDryRun {
+                remote: rev,
+                change: false,
+            },
+            PreviewTab::PushChangeDryRun => VjjCommand::PushDryRun {
+                remote: rev,
+                change: true,
+            },
+            PreviewTab::BranchPushDryRun => VjjCommand::BranchPushDryRun(rev),
+            PreviewTab::Immutable => VjjCommand::Guarded,
+            PreviewTab::Binding => VjjCommand::Binding(rev),
+            PreviewTab::Command => VjjCommand::CommandPreview(rev),
+        }
+    }
+
+    /// The jj arguments rendering the tab for `rev`, along with the kind of the cache entry when
+    /// `cached` and the output only depends on the commit. `None` for tabs that aren't rendered by
+    /// jj. The cached `show` tab only renders the diff, the header with the moving branches is
+    /// rendered by [`PreviewTab::header_args`].
+    pub fn jj_args(&self, rev: &str, cached: bool) -> Option<(Vec<String>, Option<String>)> {
+        let args = |args: &[&str]| {
+            ["--ignore-working-copy"]
+                .iter()
+                .chain(args)
+                .map(|arg| arg.to_string())
+                .collect_vec()
+        };
+        Some(match self {
+            PreviewTab::Show => {
+                let format = DiffFormat::current();
+                match format.cache_kind().filter(|_| cached) {
+                    Some(kind) => {
+                        let mut diff = args(&["diff", "-r", rev]);
+                        diff.extend(format.args());
+                        (diff, Some(format!("diff-{kind}")))
+                    }
+                    None => {
+                        let mut show = args(&["show", rev]);
+                        show.extend(format.args());
+                        (show, None)
+                    }
+                }
+            }
+            PreviewTab::DiffStat => (args(&["diff", "--stat", "-r", rev]), None),
+            PreviewTab::Obslog => (args(&["obslog", "-r", rev]), None),
+            PreviewTab::Descendants => (args(&["log", "-r", &format!("descendants({rev})")]), None),
+            PreviewTab::Conflicts => (
+                args(&["resolve", "--list", "-r", rev]),
+                Some("conflicts".to_string()).filter(|_| cached),
+            ),
+            PreviewTab::Output
+            | PreviewTab::ConflictMarkers
+            | PreviewTab::FileDiff
+            | PreviewTab::InsertAfter
+            | PreviewTab::InsertBefore
+            | PreviewTab::DryRun
+            | PreviewTab::Workspace
+            | PreviewTab::PushDryRun
+            | PreviewTab::PushChangeDryRun
+            | PreviewTab::BranchPushDryRun
+            | PreviewTab::Immutable
+            | PreviewTab::Binding
+            | PreviewTab::Command => return None,
+        })
+    }
+
+    /// The jj arguments rendering the header of `jj show` in front of a cached diff.
+    pub fn header_args(&self, rev: &str) -> Option<Vec<String>> {
+        match self {
+            PreviewTab::Show => Some(
+                [
+                    "--ignore-working-copy",
+                    "log",
+                    "--no-graph",
+                    "-r",
+                    rev,
+                    "-T",
+                    "builtin_log_detailed",
+                ]
+                .map(str::to_string)
+                .to_vec(),
+            ),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            PreviewTab::Show => ["show"]
+                .into_iter()
+                .chain(DiffFormat::current().args().iter().map(String::as_str))
+                .join(" "),
+            PreviewTab::DiffStat => "diff stat".to_string(),
+            PreviewTab::Obslog => "obslog".to_string(),
+            PreviewTab::Descendants => "descendants".to_string(),
+            PreviewTab::Conflicts => "conflicts".to_string(),
+            PreviewTab::Output => "output".to_string(),
+            PreviewTab::ConflictMarkers => "conflict markers".to_string(),
+            PreviewTab::FileDiff => "diff".to_string(),
+            PreviewTab::InsertAfter => "insert after".to_string(),
+            PreviewTab::InsertBefore => "insert before".to_string(),
+            PreviewTab::Workspace => "working copy".to_string(),
+            PreviewTab::PushDryRun => "push".to_string(),
+            PreviewTab::PushChangeDryRun => "push --change".to_string(),
+            PreviewTab::BranchPushDryRun => "push --branch".to_string(),
+            PreviewTab::Immutable => "immutable revisions".to_string(),
+            PreviewTab::Binding => "actions".to_string(),
+            PreviewTab::Command => "command".to_string(),
+            PreviewTab::DryRun => match Session::load().pending {
+                Some(args) => format!("dry run: jj {}", args.join(" ")),
+                None => "dry run".to_string(),
+            },
+        }
+    }
+
+    /// Preview label listing the tabs of the mode with the active one highlighted.
+    pub fn preview_label(&self, mode: &Mode) -> String {
+        let tabs = Self::tabs(mode);
+        if tabs.len() < 2 {
+            return format!("Preview ({})", self.name());
+        }
+        tabs.iter()
+            .map(|tab| match tab == self {
+                true => format!("[{}]", tab.name()),
+                false => tab.name(),
+            })
+            .join(" │ ")
+    }
+}
+
+/// Shows the active tab for `commit`, or refreshes the current preview when nothing is focused.
+pub fn preview_actions(mode: &Mode, commit: Option<String>) -> Vec<FzfAction> {
+    let tab = PreviewTab::current(mode);
+    vec![
+        match commit {
+            Some(commit) => FzfAction::ChangePreview(tab.command(commit)),
+            None => FzfAction::RefreshPreview,
+        },
+        FzfAction::ChangePreviewLabel(tab.preview_label(mode)),
+    ]
+}
@@ -0,0 +1,90 @@
+use std::io::{self, Write};
+
+use ansi_term::Color;
+
+use itertools::Itertools;
+
+use super::command::print_line;
+use super::exec::{jj_output, jj_plain_output};
+use crate::common::VjjResult;
+
+/// Lists the git remotes of the repo, identified by their name.
+pub fn print_remotes() -> VjjResult<()> {
+    let output = jj_plain_output(["--ignore-working-copy", "git", "remote", "list"])?;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        // e.g. `origin https://github.com/martinvonz/jj`
+        let (name, url) = line.split_once(' ').unwrap_or((line, ""));
+        print_line(name, &format!("{} {url}", Color::Purple.paint(name)))?;
+    }
+    io::stderr().write_all(&output.stderr)?;
+    Ok(())
+}
+
+/// `jj git push` arguments pushing the branches of `rev` to `remote`, or creating branches for
+/// the changes of `rev` with `change`. `--change` only takes several revisions with `all:`.
+pub fn push_args(remote: &str, rev: &str, change: bool) -> Vec<String> {
+    let revisions = match change {
+        true => format!("--change=all:{rev}"),
+        false => format!("-r={rev}"),
+    };
+    vec![
+        "git".to_string(),
+        "push".to_string(),
+        "--remote".to_string(),
+        remote.trim_matches('\'').to_string(),
+        revisions,
+    ]
+}
+
+/// Shows what `jj git push` would push to `remote`, without pushing.
+pub fn print_push_dry_run(remote: &str, rev: &str, change: bool) -> VjjResult<()> {
+    if remote.trim_matches('\'').is_empty() {
+        return Ok(());
+    }
+    let mut args = push_args(remote, rev, change);
+    args.push("--dry-run".to_string());
+    let output = jj_output(args)?;
+    io::stdout().write_all(&[output.stdout, output.stderr].concat())?;
+    Ok(())
+}
+
+/// Lists the local branches of the revisions of `rev`, identified by their name.
+pub fn print_branches(rev: &str) -> VjjResult<()> {
+    let output = jj_plain_output([
+        "--ignore-working-copy",
+        "log",
+        "--no-graph",
+        "-r",
+        rev,
+        "-T",
+        r#"local_branches.map(|b| b.name() ++ "\n").join("")"#,
+    ])?;
+    for name in String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|name| !name.is_empty())
+        .unique()
+    {
+        print_line(name, &Color::Purple.paint(name).to_string())?;
+    }
+    io::stderr().write_all(&output.stderr)?;
+    Ok(())
+}
+
+/// Shows what pushing `branch` to `remote` would do, without pushing.
+pub fn print_branch_push_dry_run(remote: &str, branch: &str) -> VjjResult<()> {
+    let branch = branch.trim_matches('\'');
+    if branch.is_empty() {
+        return Ok(());
+    }
+    let output = jj_output([
+        "git",
+        "push",
+        "--remote",
+        remote,
+        "--branch",
+        branch,
+        "--dry-run",
+    ])?;
+    io::stdout().write_all(&[output.stdout, output.stderr].concat())?;
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use itertools::Itertools;
+
+use super::exec::jj_plain_output;
+use super::focus::log_ids;
+use crate::common::{VjjError, VjjResult};
+use crate::fzf::FzfAction;
+
+/// Everything a search looks at, one revision per line. The ids are separated from the rest, they
+/// only match from their start.
+const SEARCH_TEMPLATE: &str = r#"change_id ++ "\0" ++ commit_id ++ "\0" ++ separate(" ", branches, author, description.first_line()) ++ "\n""#;
+
+/// fzf actions moving the cursor to the next revision of the log after `focused` matching `text`,
+/// or the previous one with `backwards`, wrapping around at the ends.
+///
+/// Change and commit ids starting with `text` match, as do branches, the author and the first line
+/// of the description containing it, ignoring case unless `text` contains uppercase letters.
+pub fn search(text: &str, focused: Option<&str>, backwards: bool) -> VjjResult<Vec<FzfAction>> {
+    let ids = log_ids()?;
+    let revset = ids.iter().flatten().unique().join("|");
+    if revset.is_empty() {
+        return Ok(vec![]);
+    }
+    let output = jj_plain_output([
+        "--ignore-working-copy",
+        "log",
+        "--no-graph",
+        "-r",
+        revset.as_str(),
+        "-T",
+        SEARCH_TEMPLATE,
+    ])?;
+    if !output.status.success() {
+        return Err(VjjError::Jujutsu(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    let ignore_case = !text.chars().any(char::is_uppercase);
+    let needle = match ignore_case {
+        true => text.to_lowercase(),
+        false => text.to_string(),
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let matching = stdout
+        .lines()
+        .filter_map(|line| {
+            let line = match ignore_case {
+                true => line.to_lowercase(),
+                false => line.to_string(),
+            };
+            let (change_id, commit_id, text) = line.splitn(3, '\0').collect_tuple()?;
+            (change_id.starts_with(&needle)
+                || commit_id.starts_with(&needle)
+                || text.contains(&needle))
+            .then(|| change_id.to_string())
+        })
+        .collect_vec();
+
+    let positions = ids
+        .iter()
+        .positions(|id| {
+            id.as_ref()
+                .is_some_and(|id| matching.iter().any(|change_id| change_id.starts_with(id)))
+        })
+        .collect_vec();
+    let current = ids
+        .iter()
+        .position(|id| id.is_some() && id.as_deref() == focused);
+    let next = match (backwards, current) {
+        (false, Some(current)) => positions.iter().find(|&&position| position > current),
+        (true, Some(current)) => positions.iter().rev().find(|&&position| position < current),
+        _ => None,
+    }
+    .or(match backwards {
+        false => positions.first(),
+        true => positions.last(),
+    });
+    match next {
+        // fzf positions start at 1.
+        Some(position) => Ok(vec![FzfAction::Pos(position + 1)]),
+        None => Err(VjjError::NoMatch(text.to_string())),
+    }
+}
+
+/// Revset of the revisions whose description, author or branches contain `text`.
+pub fn search_revset(text: &str) -> String {
+    let text = format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+    format!("description(substring:{text}) | author(substring:{text}) | branches(substring:{text})")
+}
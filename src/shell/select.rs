@@ -0,0 +1,133 @@
+use std::collections::HashSet;
+
+use itertools::Itertools;
+
+use super::exec::jj_plain_output;
+use super::focus::log_ids;
+use crate::common::{VjjError, VjjResult};
+use crate::config::get_config;
+use crate::fzf::FzfAction;
+
+/// Length of the full change ids jj prints for `change_id`.
+const FULL_CHANGE_ID_LEN: usize = 32;
+
+/// fzf actions adding the lines of the revisions in `revset` to the selection, then moving the
+/// cursor back to `focused`.
+pub fn select_revset(revset: &str, focused: Option<&str>) -> VjjResult<Vec<FzfAction>> {
+    let log_ids = log_ids()?;
+    let displayed = log_ids.iter().flatten().unique().join("|");
+    if displayed.is_empty() {
+        return Ok(vec![]);
+    }
+    // Only the displayed revisions can be selected, don't let jj list the rest of the revset.
+    let output = jj_plain_output([
+        "--ignore-working-copy",
+        "log",
+        "--no-graph",
+        "-r",
+        &format!("({revset}) & ({displayed})"),
+        "-T",
+        r#"change_id ++ "\n""#,
+    ])?;
+    if !output.status.success() {
+        return Err(VjjError::Jujutsu(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let matching = stdout.lines().collect::<HashSet<_>>();
+
+    let mut actions = vec![];
+    let mut focused_position = None;
+    for (index, id) in log_ids.into_iter().enumerate() {
+        let Some(id) = id else {
+            continue;
+        };
+        // fzf positions start at 1.
+        let position = index + 1;
+        // Templates showing shortened ids need a prefix match.
+        if matching.contains(id.as_str())
+            || (id.len() < FULL_CHANGE_ID_LEN
+                && matching.iter().any(|change_id| change_id.starts_with(&id)))
+        {
+            actions.extend([FzfAction::Pos(position), FzfAction::Select]);
+        }
+        if focused == Some(id.as_str()) {
+            focused_position = Some(position);
+        }
+    }
+    actions.extend(focused_position.map(FzfAction::Pos));
+    Ok(actions)
+}
+
+/// fzf actions toggling the selection of every line with a revision, then moving the cursor back
+/// to `focused`. fzf's `toggle-all` would also select descriptions and graph edges.
+pub fn invert_selection(focused: Option<&str>) -> VjjResult<Vec<FzfAction>> {
+    let mut actions = vec![];
+    let mut focused_position = None;
+    for (index, id) in log_ids()?.into_iter().enumerate() {
+        let Some(id) = id else {
+            continue;
+        };
+        // fzf positions start at 1.
+        let position = index + 1;
+        actions.extend([FzfAction::Pos(position), FzfAction::Toggle]);
+        if focused == Some(id.as_str()) {
+            focused_position = Some(position);
+        }
+    }
+    actions.extend(focused_position.map(FzfAction::Pos));
+    Ok(actions)
+}
+
+/// The change id in a line of the log, `None` for lines like descriptions and graph edges.
+pub fn line_change_id(line: &str) -> VjjResult<Option<String>> {
+    let config = get_config()?;
+    Ok(strip_ansi(line)
+        .split(config.log.delimiter.as_str())
+        .nth(config.log.change_field.saturating_sub(1))
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_string))
+}
+
+/// Removes the color codes fzf ignores with `--ansi` before splitting lines into fields.
+pub fn strip_ansi(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(char) = chars.next() {
+        if char != '\x1b' {
+            stripped.push(char);
+            continue;
+        }
+        // Skip `ESC [ <parameters> <final byte>`.
+        if chars.next() == Some('[') {
+            for char in chars.by_ref() {
+                if ('@'..='~').contains(&char) {
+                    break;
+                }
+            }
+        }
+    }
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_ansi_removes_color_codes() {
+        assert_eq!(strip_ansi("\x1b[1m\x1b[38;5;5mabc\x1b[0m def"), "abc def");
+        assert_eq!(strip_ansi("plain"), "plain");
+        assert_eq!(strip_ansi(""), "");
+    }
+
+    #[test]
+    fn strip_ansi_keeps_multibyte_characters() {
+        assert_eq!(
+            strip_ansi("@  \u{200B}\x1b[35mabc\x1b[0m\u{200B}"),
+            "@  \u{200B}abc\u{200B}"
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use ansi_term::Color;
+
+use super::command::print_line;
+use super::exec::jj_plain_output;
+use crate::common::VjjResult;
+use crate::session::Session;
+
+/// A workspace as listed by `jj workspace list`.
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    pub name: String,
+    /// Short id of the working-copy commit.
+    pub commit: String,
+    /// The rest of the line, the change id and description of the working-copy commit.
+    pub summary: String,
+}
+
+impl Workspace {
+    pub fn list() -> VjjResult<Vec<Workspace>> {
+        let output = jj_plain_output(["--ignore-working-copy", "workspace", "list"])?;
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| {
+                // e.g. `default: qpvuntsm 230dd059 (no description set)`
+                let (name, summary) = line.split_once(": ")?;
+                let commit = summary.split_whitespace().nth(1)?;
+                Some(Workspace {
+                    name: name.to_string(),
+                    commit: commit.to_string(),
+                    summary: summary.to_string(),
+                })
+            })
+            .collect())
+    }
+
+    /// The workspace vjj was started in, found by its working-copy commit.
+    pub fn current() -> Option<String> {
+        let output = jj_plain_output([
+            "--ignore-working-copy",
+            "log",
+            "--no-graph",
+            "-r",
+            "@",
+            "-T",
+            "commit_id",
+        ])
+        .ok()?;
+        let commit = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Workspace::list()
+            .ok()?
+            .into_iter()
+            .find(|workspace| !workspace.commit.is_empty() && commit.starts_with(&workspace.commit))
+            .map(|workspace| workspace.name)
+    }
+
+    /// The workspace targeted by `{workspace:wc}`, `None` for the current one.
+    pub fn target() -> Option<String> {
+        Session::load().workspace
+    }
+
+    /// Revision of the working-copy commit of the targeted workspace.
+    pub fn target_wc() -> String {
+        match Self::target() {
+            Some(name) => format!("{name}@"),
+            None => "@".to_string(),
+        }
+    }
+}
+
+/// Lists the workspaces, identified by their name.
+pub fn print_workspaces() -> VjjResult<()> {
+    let current = Workspace::current();
+    let target = Workspace::target();
+    for workspace in Workspace::list()? {
+        let marker = match (
+            current.as_ref() == Some(&workspace.name),
+            target.as_ref() == Some(&workspace.name),
+        ) {
+            (true, _) => " (current)",
+            (_, true) => " (target)",
+            _ => "",
+        };
+        print_line(
+            &workspace.name,
+            &format!(
+                "{}{marker}: {}",
+                Color::Purple.paint(&workspace.name),
+                workspace.summary
+            ),
+        )?;
+    }
+    Ok(())
+}